@@ -0,0 +1,231 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{ws, Request},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use futures_util::{SinkExt, StreamExt, TryStreamExt};
+use tokio_tungstenite::tungstenite;
+
+use crate::{manager::Manager, service};
+
+use super::generic;
+
+// Headers of the hop or of the manager, not forwarded
+const SKIP_HEADERS: [&str; 14] = [
+    "host",
+    "authorization",
+    "origin",
+    "connection",
+    "upgrade",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "content-length",
+    "cookie",
+];
+
+fn skip_header(name: &http::HeaderName) -> bool {
+    SKIP_HEADERS.contains(&name.as_str())
+        || name.as_str().starts_with("sec-websocket-")
+        || name.as_str().starts_with("access-control-")
+}
+
+// Path and query to request on the core: ../clash prefix and manager auth removed
+fn clash_path_and_query(uri: &http::Uri) -> String {
+    let mut path = uri.path();
+    if let Some(p) = path.strip_prefix("/instance/") {
+        path = p.find('/').map(|i| &p[i..]).unwrap_or("");
+    }
+    let path = match path.strip_prefix("/clash").unwrap_or(path) {
+        "" => "/",
+        p => p,
+    };
+    let query = uri
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|s| !s.is_empty())
+        .filter(|s| {
+            let k = s.split_once('=').map(|(k, _)| k).unwrap_or(s);
+            k != "token" && k != "secret"
+        })
+        .collect::<Vec<_>>();
+    if query.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}?{}", path, query.join("&"))
+    }
+}
+
+// Clash API: (Any) ../clash/*path
+// Forwards HTTP and Websocket requests to the clash api of the running core
+pub(crate) async fn proxy(
+    instance: generic::ServiceInstance,
+    ws: Option<ws::WebSocketUpgrade>,
+    state: axum::extract::State<Arc<Manager>>,
+    req: Request,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&state) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let client = match service.clash_api_client() {
+        Ok(v) => v,
+        Err(e) => return generic::service_error_to_http_response(e).into_response(),
+    };
+    let path_and_query = clash_path_and_query(req.uri());
+    match ws {
+        Some(ws) => proxy_websocket(client, ws, &path_and_query).await,
+        None => proxy_http(client, req, &path_and_query).await,
+    }
+}
+
+async fn proxy_http(
+    client: service::ClashAPIClient,
+    req: Request,
+    path_and_query: &str,
+) -> Response {
+    let (parts, body) = req.into_parts();
+    let mut builder = client.forward_request(parts.method, path_and_query);
+    for (name, value) in parts.headers.iter() {
+        if !skip_header(name) {
+            builder = builder.header(name, value);
+        }
+    }
+    // Request bodies of the clash api are small json
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(v) => v,
+        Err(e) => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string())
+                .into_response()
+        }
+    };
+    let resp = match builder.body(body).send().await {
+        Ok(v) => v,
+        Err(e) => {
+            return generic::ErrorResponse::new(
+                StatusCode::BAD_GATEWAY,
+                format!("clash api request failed: {}", e),
+            )
+            .into_response()
+        }
+    };
+    let mut response = Response::builder().status(resp.status());
+    for (name, value) in resp.headers().iter() {
+        if !skip_header(name) {
+            response = response.header(name, value);
+        }
+    }
+    response
+        .body(Body::from_stream(resp.bytes_stream()))
+        .unwrap_or_else(|e| {
+            generic::ErrorResponse::new(StatusCode::BAD_GATEWAY, e.to_string()).into_response()
+        })
+}
+
+async fn proxy_websocket(
+    client: service::ClashAPIClient,
+    ws: ws::WebSocketUpgrade,
+    path_and_query: &str,
+) -> Response {
+    let req = match client.forward_websocket_request(path_and_query) {
+        Ok(v) => v,
+        Err(e) => return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    // Connect before upgrading, so a failure is still an HTTP error
+    let upstream = match tokio_tungstenite::connect_async(req).await {
+        Ok((v, _)) => v,
+        Err(e) => {
+            return generic::ErrorResponse::new(
+                StatusCode::BAD_GATEWAY,
+                format!("clash api websocket failed: {}", e),
+            )
+            .into_response()
+        }
+    };
+    ws.on_upgrade(move |socket| async move {
+        let (mut upstream_sink, mut upstream_stream) = upstream.split();
+        let (mut sink, mut stream) = socket.split();
+        let to_core = async {
+            while let Ok(Some(msg)) = stream.try_next().await {
+                let msg = match msg {
+                    ws::Message::Text(s) => tungstenite::Message::Text(s),
+                    ws::Message::Binary(b) => tungstenite::Message::Binary(b),
+                    ws::Message::Ping(b) => tungstenite::Message::Ping(b),
+                    ws::Message::Pong(b) => tungstenite::Message::Pong(b),
+                    ws::Message::Close(_) => break,
+                };
+                if upstream_sink.send(msg).await.is_err() {
+                    break;
+                }
+            }
+            let _ = upstream_sink.close().await;
+        };
+        let to_client = async {
+            while let Some(Ok(msg)) = upstream_stream.next().await {
+                let msg = match msg {
+                    tungstenite::Message::Text(s) => ws::Message::Text(s),
+                    tungstenite::Message::Binary(b) => ws::Message::Binary(b),
+                    tungstenite::Message::Ping(b) => ws::Message::Ping(b),
+                    tungstenite::Message::Pong(b) => ws::Message::Pong(b),
+                    tungstenite::Message::Close(_) => break,
+                    tungstenite::Message::Frame(_) => continue,
+                };
+                if sink.send(msg).await.is_err() {
+                    break;
+                }
+            }
+            let _ = sink.send(ws::Message::Close(None)).await;
+        };
+        // Either side closing ends the proxy
+        tokio::select! {
+          _ = to_core => {},
+          _ = to_client => {},
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path_and_query(uri: &str) -> String {
+        clash_path_and_query(&uri.parse().unwrap())
+    }
+
+    #[test]
+    fn test_clash_path_and_query() {
+        assert_eq!(path_and_query("/clash"), "/");
+        assert_eq!(path_and_query("/clash/"), "/");
+        assert_eq!(path_and_query("/clash/proxies/GLOBAL"), "/proxies/GLOBAL");
+        assert_eq!(path_and_query("/instance/hk/clash"), "/");
+        assert_eq!(
+            path_and_query("/instance/hk/clash/connections"),
+            "/connections"
+        );
+        // The manager auth is not forwarded, other params are kept in order
+        assert_eq!(
+            path_and_query("/clash/connections?token=s&interval=1000"),
+            "/connections?interval=1000"
+        );
+        assert_eq!(
+            path_and_query("/clash/proxies/a/delay?url=http%3A%2F%2Fx&secret=s&timeout=5000"),
+            "/proxies/a/delay?url=http%3A%2F%2Fx&timeout=5000"
+        );
+        assert_eq!(
+            path_and_query("/clash/traffic?token=s&secret=s"),
+            "/traffic"
+        );
+        assert_eq!(
+            path_and_query("/clash/logs?level=info&&"),
+            "/logs?level=info"
+        );
+    }
+}
@@ -157,3 +157,181 @@ pub(crate) async fn get_active_config(ctx: generic::RequestRawBodyContext) -> im
         Err(e) => generic::db_error_to_http_response(e).into_response(),
     }
 }
+
+// Get Config Launch Option: GET ../config/:id/launch_option
+pub(crate) async fn get_launch_option(
+    ctx: generic::RequestRawBodyContext<String>,
+) -> impl IntoResponse {
+    let id = match ctx.path_params {
+        Some(p) => p.0,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing id")
+                .into_response();
+        }
+    };
+    match database::get_config_launch_option(&ctx.manager.get_database(), id).await {
+        Ok(v) => generic::GenericResponse::new(StatusCode::OK, v).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Set Config Launch Option: PUT ../config/:id/launch_option
+// Applied on the next start, merged with the global launch option
+pub(crate) async fn set_launch_option(
+    ctx: generic::RequestJsonContext<String, database::LaunchOption>,
+) -> impl IntoResponse {
+    let id = match ctx.path_params {
+        Some(p) => p.0,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing id")
+                .into_response();
+        }
+    };
+    match database::set_config_launch_option(&ctx.manager.get_database(), id, ctx.body.0).await {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Delete Config Launch Option: DELETE ../config/:id/launch_option
+pub(crate) async fn delete_launch_option(
+    ctx: generic::RequestRawBodyContext<String>,
+) -> impl IntoResponse {
+    let id = match ctx.path_params {
+        Some(p) => p.0,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing id")
+                .into_response();
+        }
+    };
+    match database::delete_config_launch_option(&ctx.manager.get_database(), id).await {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// List Config Proxy Selection: GET ../config/:id/proxy_selection
+pub(crate) async fn list_proxy_selection(
+    ctx: generic::RequestRawBodyContext<String>,
+) -> impl IntoResponse {
+    let id = match ctx.path_params {
+        Some(p) => p.0,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing id")
+                .into_response();
+        }
+    };
+    match database::list_proxy_selection(&ctx.manager.get_database(), id).await {
+        Ok(v) => generic::GenericResponse::new(StatusCode::OK, v).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct DeleteProxySelectionQuery {
+    group: Option<String>,
+}
+
+// Delete Config Proxy Selection: DELETE ../config/:id/proxy_selection (params: ?group=<group>)
+// Forget the saved selection of one group, or of all groups without group
+pub(crate) async fn delete_proxy_selection(
+    ctx: generic::RequestRawBodyContext<String>,
+) -> impl IntoResponse {
+    let query = match axum::extract::Query::<DeleteProxySelectionQuery>::try_from_uri(ctx.req.uri())
+    {
+        Ok(q) => q.0,
+        Err(e) => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string())
+                .into_response();
+        }
+    };
+    let id = match ctx.path_params {
+        Some(p) => p.0,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing id")
+                .into_response();
+        }
+    };
+    match database::delete_proxy_selection(&ctx.manager.get_database(), id, query.group).await {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Get Config Clash Mode: GET ../config/:id/clash_mode
+pub(crate) async fn get_clash_mode(
+    ctx: generic::RequestRawBodyContext<String>,
+) -> impl IntoResponse {
+    let id = match ctx.path_params {
+        Some(p) => p.0,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing id")
+                .into_response();
+        }
+    };
+    match database::get_clash_mode(&ctx.manager.get_database(), id).await {
+        Ok(v) => generic::GenericResponse::new(StatusCode::OK, v).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Delete Config Clash Mode: DELETE ../config/:id/clash_mode
+// The core starts in the mode of its config again
+pub(crate) async fn delete_clash_mode(
+    ctx: generic::RequestRawBodyContext<String>,
+) -> impl IntoResponse {
+    let id = match ctx.path_params {
+        Some(p) => p.0,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing id")
+                .into_response();
+        }
+    };
+    match database::delete_clash_mode(&ctx.manager.get_database(), id).await {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Get Config Traffic Total: GET ../config/:id/traffic_total
+pub(crate) async fn get_traffic_total(
+    ctx: generic::RequestRawBodyContext<String>,
+) -> impl IntoResponse {
+    let id = match ctx.path_params {
+        Some(p) => p.0,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing id")
+                .into_response();
+        }
+    };
+    match database::get_traffic_total(&ctx.manager.get_database(), id).await {
+        Ok(v) => generic::GenericResponse::new(StatusCode::OK, v).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Reset Config Traffic Total: DELETE ../config/:id/traffic_total
+pub(crate) async fn reset_traffic_total(
+    ctx: generic::RequestRawBodyContext<String>,
+) -> impl IntoResponse {
+    let id = match ctx.path_params {
+        Some(p) => p.0,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing id")
+                .into_response();
+        }
+    };
+    if id == database::GLOBAL_TRAFFIC_TOTAL {
+        return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "invalid id").into_response();
+    }
+    match database::reset_traffic_total(
+        &ctx.manager.get_database(),
+        id,
+        chrono::Utc::now().timestamp_millis(),
+    )
+    .await
+    {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
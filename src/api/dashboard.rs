@@ -0,0 +1,213 @@
+use std::{path::PathBuf, sync::Arc};
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Redirect},
+};
+use futures_util::TryStreamExt;
+use tokio::{fs, io};
+
+use crate::{common, manager};
+
+use super::generic;
+
+#[derive(serde::Serialize)]
+pub(crate) struct DashboardResponseBody {
+    name: String,
+    path: String,
+}
+
+impl DashboardResponseBody {
+    fn new(name: String) -> Self {
+        Self {
+            path: format!("/dashboard/{}/", name),
+            name,
+        }
+    }
+}
+
+// Upload Dashboard: POST ../dashboard/:name (multipart, zip or tar.gz)
+pub(crate) async fn upload_dashboard(
+    name: axum::extract::Path<String>,
+    mut ctx: generic::RequestMultiPartContext,
+) -> impl IntoResponse {
+    let name = name.0;
+    if let Err(e) = manager::check_dashboard_name(&name) {
+        return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e).into_response();
+    }
+    let field = match ctx.multipart.next_field().await {
+        Ok(Some(v)) => v,
+        _ => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing file data")
+                .into_response();
+        }
+    };
+    let archive = ctx.manager.get_temp_dir_path().join(format!(
+        "dashboard-{}.temp",
+        common::random_uuid().replace('-', "")
+    ));
+    let res = match save_archive(&archive, field).await {
+        Ok(_) => {
+            let data_dir = ctx.manager.get_data_dir_path().clone();
+            let (name, archive) = (name.clone(), archive.clone());
+            // Unpacking is blocking io
+            tokio::task::spawn_blocking(move || {
+                manager::install_dashboard(&data_dir, &name, &archive)
+            })
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()))
+        }
+        Err(e) => Err(e),
+    };
+    let _ = fs::remove_file(&archive).await;
+    match res {
+        Ok(_) => {
+            log::info!("dashboard {} installed", name);
+            generic::GenericResponse::new(StatusCode::OK, DashboardResponseBody::new(name))
+                .into_response()
+        }
+        Err(e) => generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn save_archive(
+    path: &PathBuf,
+    field: axum::extract::multipart::Field<'_>,
+) -> Result<(), String> {
+    let mut stream_reader = tokio_util::io::StreamReader::new(field.map_err(io::Error::other));
+    let file = fs::File::create(path)
+        .await
+        .map_err(|e| format!("failed to create temp file: {}", e))?;
+    let mut file_buf_writer = io::BufWriter::new(file);
+    io::copy(&mut stream_reader, &mut file_buf_writer)
+        .await
+        .map_err(|e| format!("failed to receive dashboard: {}", e))?;
+    io::AsyncWriteExt::flush(&mut file_buf_writer)
+        .await
+        .map_err(|e| format!("failed to receive dashboard: {}", e))
+}
+
+// List Dashboard: GET ../dashboard
+pub(crate) async fn list_dashboard(ctx: generic::RequestRawBodyContext<()>) -> impl IntoResponse {
+    match manager::list_dashboard(ctx.manager.get_data_dir_path()) {
+        Ok(v) => generic::GenericResponse::new(
+            StatusCode::OK,
+            v.into_iter()
+                .map(DashboardResponseBody::new)
+                .collect::<Vec<_>>(),
+        )
+        .into_response(),
+        Err(e) => generic::ErrorResponse::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            .into_response(),
+    }
+}
+
+// Delete Dashboard: DELETE ../dashboard/:name
+pub(crate) async fn delete_dashboard(
+    ctx: generic::RequestRawBodyContext<String>,
+) -> impl IntoResponse {
+    let name = match ctx.path_params {
+        Some(p) => p.0,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing name")
+                .into_response();
+        }
+    };
+    if let Err(e) = manager::check_dashboard_name(&name) {
+        return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e).into_response();
+    }
+    match manager::delete_dashboard(ctx.manager.get_data_dir_path(), &name) {
+        Ok(true) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Ok(false) => generic::ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            format!("dashboard not found: {}", name),
+        )
+        .into_response(),
+        Err(e) => generic::ErrorResponse::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            .into_response(),
+    }
+}
+
+// Serve Dashboard: GET /dashboard/:name/*path (no auth, like the embedded ui)
+// The clash api is proxied at /api/v1/clash, so the controller is the host serving the dashboard:
+// index.html of yacd gets it as `data-base-url`, other dashboards are redirected on the first
+// visit with `?hostname=&port=&secondaryPath=`, which metacubexd and zashboard read on setup
+pub(crate) async fn serve_dashboard(
+    params: axum::extract::Path<Vec<(String, String)>>,
+    state: axum::extract::State<Arc<manager::Manager>>,
+    req: axum::extract::Request,
+) -> impl IntoResponse {
+    let mut name = String::new();
+    let mut path = String::new();
+    for (k, v) in params.0 {
+        match k.as_str() {
+            "name" => name = v,
+            "path" => path = v,
+            _ => {}
+        }
+    }
+    if manager::check_dashboard_name(&name).is_err() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let index = path.is_empty() || path == "index.html";
+    let host = req
+        .headers()
+        .get(http::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("127.0.0.1")
+        .to_string();
+    let (hostname, port) = match manager::split_host(&host) {
+        Some(v) => v,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "invalid host")
+                .into_response();
+        }
+    };
+    let scheme = match req
+        .headers()
+        .get("X-Forwarded-Proto")
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(v) if v.eq_ignore_ascii_case("https") => "https",
+        _ => "http",
+    };
+    let file = match manager::dashboard_file(
+        state.get_data_dir_path(),
+        &name,
+        if index { "index.html" } else { &path },
+    ) {
+        Some(v) => v,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+    // The file must stay in the dashboard after resolving links
+    let root = manager::dashboard_dir(state.get_data_dir_path()).join(&name);
+    let file = match (fs::canonicalize(&file).await, fs::canonicalize(&root).await) {
+        (Ok(file), Ok(root)) if file.starts_with(&root) => file,
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let data = match fs::read(&file).await {
+        Ok(v) => v,
+        Err(_) => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let mime_type = mime_guess::from_path(&file).first_or_octet_stream();
+    if index {
+        let controller = format!("{}://{}/api/v1/clash", scheme, host);
+        match manager::inject_controller(&String::from_utf8_lossy(&data), &controller) {
+            Some(index) => {
+                return ([(http::header::CONTENT_TYPE, mime_type.as_ref())], index).into_response()
+            }
+            None if path.is_empty() && req.uri().query().is_none() => {
+                let port = port.unwrap_or(if scheme == "https" { 443 } else { 80 });
+                return Redirect::temporary(&format!(
+                    "/dashboard/{}/?hostname={}&port={}&secondaryPath=/api/v1/clash",
+                    name,
+                    manager::encode_query_value(hostname),
+                    manager::encode_query_value(&port.to_string())
+                ))
+                .into_response();
+            }
+            None => {}
+        }
+    }
+    ([(http::header::CONTENT_TYPE, mime_type.as_ref())], data).into_response()
+}
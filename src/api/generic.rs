@@ -2,13 +2,13 @@ use std::sync::Arc;
 
 use axum::{
     body::Body,
-    extract::{FromRequest, Path, Request},
+    extract::{FromRequest, FromRequestParts, Path, RawPathParams, Request},
     http::{Response, StatusCode},
     response::IntoResponse,
     Json, RequestExt,
 };
 
-use crate::{database, manager};
+use crate::{database, manager, service};
 
 #[derive(serde::Serialize)]
 pub(crate) struct ErrorResponse {
@@ -161,6 +161,47 @@ impl FromRequest<Arc<manager::Manager>> for RequestMultiPartContext {
     }
 }
 
+// Service Instance: the `:name` path param of instance scoped routes, None for the default instance
+pub(crate) struct ServiceInstance(pub(crate) Option<String>);
+
+#[async_trait::async_trait]
+impl FromRequestParts<Arc<manager::Manager>> for ServiceInstance {
+    type Rejection = ();
+
+    async fn from_request_parts(
+        parts: &mut http::request::Parts,
+        state: &Arc<manager::Manager>,
+    ) -> Result<Self, Self::Rejection> {
+        let name = RawPathParams::from_request_parts(parts, state)
+            .await
+            .ok()
+            .and_then(|params| {
+                params
+                    .iter()
+                    .find(|(k, _)| *k == "name")
+                    .map(|(_, v)| v.to_string())
+            });
+        Ok(Self(name))
+    }
+}
+
+impl ServiceInstance {
+    pub(crate) fn get_service(
+        &self,
+        manager: &manager::Manager,
+    ) -> Result<service::Service, ErrorResponse> {
+        match &self.0 {
+            Some(name) => manager.get_instance_service(name).ok_or_else(|| {
+                ErrorResponse::new(
+                    StatusCode::NOT_FOUND,
+                    format!("instance not found: {}", name),
+                )
+            }),
+            None => Ok(manager.get_service()),
+        }
+    }
+}
+
 pub(crate) fn db_error_to_http_response(e: database::Error) -> impl IntoResponse {
     match &e {
         database::Error::DBError(e) => {
@@ -169,3 +210,24 @@ pub(crate) fn db_error_to_http_response(e: database::Error) -> impl IntoResponse
         _ => ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string()).into_response(),
     }
 }
+
+pub(crate) fn service_error_to_http_response(e: service::Error) -> impl IntoResponse {
+    match &e {
+        service::Error::Busy(_) => {
+            ErrorResponse::new(StatusCode::CONFLICT, e.to_string()).into_response()
+        }
+        service::Error::NoPendingConfirm => {
+            ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+        service::Error::ServiceNotRunning => {
+            ErrorResponse::new(StatusCode::CONFLICT, e.to_string()).into_response()
+        }
+        service::Error::ClashAPIFailed(_) => {
+            ErrorResponse::new(StatusCode::BAD_GATEWAY, e.to_string()).into_response()
+        }
+        service::Error::InvalidClashMode(_) => {
+            ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+        _ => ErrorResponse::new(StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response(),
+    }
+}
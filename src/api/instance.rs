@@ -0,0 +1,186 @@
+use axum::{http::StatusCode, response::IntoResponse};
+
+use crate::database;
+
+use super::generic;
+
+#[derive(serde::Serialize)]
+pub(crate) struct InstanceResponseBody {
+    #[serde(flatten)]
+    instance: database::Instance,
+    is_running: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct AddInstanceRequestBody {
+    name: String,
+    core_path: Option<String>,
+    config_id: Option<String>,
+    #[serde(default)]
+    auto_start: bool,
+    before_start_script: Option<String>,
+    after_start_script: Option<String>,
+    before_close_script: Option<String>,
+    after_close_script: Option<String>,
+}
+
+// Add Instance: POST ../instance
+pub(crate) async fn add_instance(
+    ctx: generic::RequestJsonContext<(), AddInstanceRequestBody>,
+) -> impl IntoResponse {
+    let body = ctx.body.0;
+    let instance = database::Instance {
+        name: body.name,
+        core_path: body.core_path,
+        config_id: body.config_id,
+        auto_start: body.auto_start,
+        before_start_script: body.before_start_script,
+        after_start_script: body.after_start_script,
+        before_close_script: body.before_close_script,
+        after_close_script: body.after_close_script,
+    };
+    match database::add_instance(&ctx.manager.get_database(), instance).await {
+        Ok(v) => {
+            ctx.manager.add_instance(v.name.clone());
+            generic::GenericResponse::new(StatusCode::OK, v).into_response()
+        }
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Get Instance: GET ../instance/:name
+pub(crate) async fn get_instance(ctx: generic::RequestRawBodyContext<String>) -> impl IntoResponse {
+    let name = match ctx.path_params {
+        Some(p) => p.0,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing name")
+                .into_response();
+        }
+    };
+    match database::get_instance(&ctx.manager.get_database(), name).await {
+        Ok(v) => {
+            let is_running = is_running(&ctx.manager, &v.name);
+            generic::GenericResponse::new(
+                StatusCode::OK,
+                InstanceResponseBody {
+                    instance: v,
+                    is_running,
+                },
+            )
+            .into_response()
+        }
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Empty string: clean the field
+#[derive(serde::Deserialize)]
+pub(crate) struct ModifyInstanceRequestBody {
+    core_path: Option<String>,
+    config_id: Option<String>,
+    auto_start: Option<bool>,
+    before_start_script: Option<String>,
+    after_start_script: Option<String>,
+    before_close_script: Option<String>,
+    after_close_script: Option<String>,
+}
+
+// Modify Instance: PATCH ../instance/:name
+pub(crate) async fn modify_instance(
+    ctx: generic::RequestJsonContext<String, ModifyInstanceRequestBody>,
+) -> impl IntoResponse {
+    let name = match ctx.path_params {
+        Some(p) => p.0,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing name")
+                .into_response();
+        }
+    };
+    let body = ctx.body.0;
+    let f = |v: Option<String>| match v {
+        Some(s) if s.is_empty() => sea_orm::ActiveValue::Set(None),
+        Some(s) => sea_orm::ActiveValue::Set(Some(s)),
+        None => sea_orm::ActiveValue::NotSet,
+    };
+    let instance = database::ActiveInstance {
+        core_path: f(body.core_path),
+        config_id: f(body.config_id),
+        auto_start: match body.auto_start {
+            Some(v) => sea_orm::ActiveValue::Set(v),
+            None => sea_orm::ActiveValue::NotSet,
+        },
+        before_start_script: f(body.before_start_script),
+        after_start_script: f(body.after_start_script),
+        before_close_script: f(body.before_close_script),
+        after_close_script: f(body.after_close_script),
+        ..Default::default()
+    };
+    match database::modify_instance(&ctx.manager.get_database(), name, instance).await {
+        Ok(v) => generic::GenericResponse::new(StatusCode::OK, v).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Delete Instance: DELETE ../instance/:name
+// The instance is stopped once deleted
+pub(crate) async fn delete_instance(
+    ctx: generic::RequestRawBodyContext<String>,
+) -> impl IntoResponse {
+    let name = match ctx.path_params {
+        Some(p) => p.0,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing name")
+                .into_response();
+        }
+    };
+    if name == database::DEFAULT_INSTANCE {
+        return generic::ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "the default instance can not be deleted",
+        )
+        .into_response();
+    }
+    let db = ctx.manager.get_database();
+    match database::delete_instance(&db, name.clone()).await {
+        Ok(_) => {}
+        Err(e @ database::Error::InstanceNotFound(_)) => {
+            return generic::ErrorResponse::new(StatusCode::NOT_FOUND, e.to_string())
+                .into_response();
+        }
+        Err(e) => return generic::db_error_to_http_response(e).into_response(),
+    }
+    if let Some(service) = ctx.manager.remove_instance(&name) {
+        let _ = service.close().await;
+        if let Err(e) = database::delete_instance_history(&*db, &name).await {
+            log::error!("instance: delete history of {} failed: {}", name, e);
+        }
+    }
+    generic::GenericResponse::new(StatusCode::OK, "success").into_response()
+}
+
+// List Instance: GET ../instance
+pub(crate) async fn list_instance(ctx: generic::RequestRawBodyContext) -> impl IntoResponse {
+    match database::list_instance(&ctx.manager.get_database()).await {
+        Ok(v) => {
+            let v = v
+                .into_iter()
+                .map(|instance| InstanceResponseBody {
+                    is_running: is_running(&ctx.manager, &instance.name),
+                    instance,
+                })
+                .collect::<Vec<_>>();
+            generic::GenericResponse::new(StatusCode::OK, v).into_response()
+        }
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+fn is_running(manager: &crate::manager::Manager, name: &str) -> bool {
+    match manager.get_instance_service(name) {
+        Some(service) => {
+            let (_, status) = service.get_status();
+            status.is_running.load(std::sync::atomic::Ordering::Relaxed)
+        }
+        None => false,
+    }
+}
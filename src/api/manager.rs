@@ -10,3 +10,15 @@ pub(crate) async fn request_to_exit(manager: State<Arc<manager::Manager>>) -> im
     manager.request_exit();
     StatusCode::NO_CONTENT.into_response()
 }
+
+// Metrics: GET /metrics (Prometheus text format)
+// Served under the root like /api/v1, without auth on the local listener
+pub(crate) async fn get_metrics(manager: State<Arc<manager::Manager>>) -> impl IntoResponse {
+    (
+        [(
+            http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        manager::render_metrics(&manager),
+    )
+}
@@ -1,5 +1,8 @@
+pub(crate) mod clash;
 pub(crate) mod config;
+pub(crate) mod dashboard;
 pub(crate) mod generic;
+pub(crate) mod instance;
 pub(crate) mod kv;
 pub(crate) mod manager;
 pub(crate) mod script;
@@ -1,6 +1,8 @@
 use std::{
+    collections::HashMap,
     path::PathBuf,
     sync::{atomic::Ordering, Arc},
+    time::Duration,
 };
 
 use axum::{http::StatusCode, response::IntoResponse};
@@ -11,30 +13,359 @@ use crate::{database, manager::Manager, service};
 
 use super::generic;
 
-// Start Service: GET ../service/start
-pub(crate) async fn start_service(ctx: generic::RequestRawBodyContext) -> impl IntoResponse {
-    match ctx.manager.get_service().start_service().await {
+#[derive(serde::Deserialize)]
+pub(crate) struct StartServiceQuery {
+    #[serde(default)]
+    wait: bool,
+    timeout: Option<u64>, // s
+}
+
+// Wait until the core is ready or has exited when ?wait=true (default timeout: ready timeout)
+async fn parse_start_service_query(
+    manager: &Manager,
+    uri: &http::Uri,
+) -> Result<Option<Duration>, axum::response::Response> {
+    let query = axum::extract::Query::<StartServiceQuery>::try_from_uri(uri)
+        .map_err(|e| {
+            generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        })?
+        .0;
+    if !query.wait {
+        return Ok(None);
+    }
+    let timeout = match query.timeout {
+        Some(t) => t,
+        None => database::get_ready_timeout(&manager.get_database())
+            .await
+            .map_err(|e| generic::db_error_to_http_response(e).into_response())?,
+    };
+    Ok(Some(Duration::from_secs(timeout)))
+}
+
+// Start Service: GET ../service/start (params: ?wait=<bool>&timeout=<secs>)
+pub(crate) async fn start_service(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let wait = match parse_start_service_query(&ctx.manager, ctx.req.uri()).await {
+        Ok(v) => v,
+        Err(response) => return response,
+    };
+    match service.start_service(wait).await {
         Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
-        Err(e) => generic::ErrorResponse::new(StatusCode::SERVICE_UNAVAILABLE, e.to_string())
-            .into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
     }
 }
 
 // Stop Service: GET ../service/stop
-pub(crate) async fn stop_service(ctx: generic::RequestRawBodyContext) -> impl IntoResponse {
-    match ctx.manager.get_service().stop_service().await {
+pub(crate) async fn stop_service(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    match service.stop_service().await {
         Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
-        Err(e) => generic::ErrorResponse::new(StatusCode::SERVICE_UNAVAILABLE, e.to_string())
-            .into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
     }
 }
 
-// Restart Service: GET ../service/restart
-pub(crate) async fn restart_service(ctx: generic::RequestRawBodyContext) -> impl IntoResponse {
-    match ctx.manager.get_service().restart_service().await {
+// Reload Service: GET ../service/reload (params: ?wait=<bool>&timeout=<secs>)
+// Reload the active config by SIGHUP, restart (and wait) when a reload is impossible
+pub(crate) async fn reload_service(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let wait = match parse_start_service_query(&ctx.manager, ctx.req.uri()).await {
+        Ok(v) => v,
+        Err(response) => return response,
+    };
+    match service.reload_service(wait).await {
         Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
-        Err(e) => generic::ErrorResponse::new(StatusCode::SERVICE_UNAVAILABLE, e.to_string())
-            .into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
+    }
+}
+
+// Restart Service: GET ../service/restart (params: ?wait=<bool>&timeout=<secs>)
+pub(crate) async fn restart_service(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let wait = match parse_start_service_query(&ctx.manager, ctx.req.uri()).await {
+        Ok(v) => v,
+        Err(response) => return response,
+    };
+    match service.restart_service(wait).await {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ApplyConfigQuery {
+    timeout: Option<u64>, // s
+    confirm: Option<u64>, // s
+}
+
+// Apply Config: PUT ../service/apply_config/:id (params: ?timeout=<secs>&confirm=<secs>)
+// Start the config and mark it as active once it is ready, roll back on failure
+// With confirm, revert to the previous config unless confirmed within the window
+pub(crate) async fn apply_config(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let id = match ctx.path_params.and_then(|mut p| p.0.remove("id")) {
+        Some(id) => id,
+        None => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing id")
+                .into_response();
+        }
+    };
+    let query = match axum::extract::Query::<ApplyConfigQuery>::try_from_uri(ctx.req.uri()) {
+        Ok(q) => q.0,
+        Err(e) => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string())
+                .into_response();
+        }
+    };
+    let timeout = match query.timeout {
+        Some(t) => t,
+        None => match database::get_ready_timeout(&ctx.manager.get_database()).await {
+            Ok(t) => t,
+            Err(e) => return generic::db_error_to_http_response(e).into_response(),
+        },
+    };
+    match service
+        .apply_config(
+            id,
+            Duration::from_secs(timeout),
+            query.confirm.map(Duration::from_secs),
+        )
+        .await
+    {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
+    }
+}
+
+// Confirm Config: GET ../service/confirm_config
+pub(crate) async fn confirm_config(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    match service.confirm_config().await {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ListRunQuery {
+    page: Option<u64>,      // start from 1
+    page_size: Option<u64>, // default 20, max 100
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct ListRunResponseBody {
+    total: u64,
+    page: u64,
+    page_size: u64,
+    runs: Vec<database::ServiceRun>,
+}
+
+// List Run: GET ../service/runs (params: ?page=<n>&page_size=<n>)
+// Run history of the core, newest first
+pub(crate) async fn list_run(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    const DEFAULT_PAGE_SIZE: u64 = 20;
+    const MAX_PAGE_SIZE: u64 = 100;
+
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let query = match axum::extract::Query::<ListRunQuery>::try_from_uri(ctx.req.uri()) {
+        Ok(q) => q.0,
+        Err(e) => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string())
+                .into_response();
+        }
+    };
+    let page = query.page.unwrap_or(1).max(1);
+    let page_size = query
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    match database::list_service_run(
+        &ctx.manager.get_database(),
+        service.get_name().to_string(),
+        page,
+        page_size,
+    )
+    .await
+    {
+        Ok((runs, total)) => generic::GenericResponse::new(
+            StatusCode::OK,
+            ListRunResponseBody {
+                total,
+                page,
+                page_size,
+                runs,
+            },
+        )
+        .into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Get Process Limit: GET ../service/process_limit
+pub(crate) async fn get_process_limit(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    match database::get_process_limit(&ctx.manager.get_database(), service.get_name().to_string())
+        .await
+    {
+        Ok(v) => generic::GenericResponse::new(StatusCode::OK, v).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Set Process Limit: PUT ../service/process_limit
+// User, capabilities, rlimits, nice and cgroup of the core, applied on the next start (linux only)
+pub(crate) async fn set_process_limit(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestJsonContext<(), database::ProcessLimit>,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    if cfg!(not(target_os = "linux")) && !ctx.body.0.is_empty() {
+        return generic::ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "process limits are only supported on linux",
+        )
+        .into_response();
+    }
+    match database::set_process_limit(
+        &ctx.manager.get_database(),
+        service.get_name().to_string(),
+        ctx.body.0,
+    )
+    .await
+    {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Delete Process Limit: DELETE ../service/process_limit
+pub(crate) async fn delete_process_limit(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    match database::delete_process_limit(
+        &ctx.manager.get_database(),
+        service.get_name().to_string(),
+    )
+    .await
+    {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Get Netns: GET ../service/netns
+pub(crate) async fn get_netns(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    match database::get_netns(&ctx.manager.get_database(), service.get_name().to_string()).await {
+        Ok(v) => generic::GenericResponse::new(StatusCode::OK, v).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Set Netns: PUT ../service/netns
+// Network namespace and veth pair of the core, applied on the next start (linux only)
+pub(crate) async fn set_netns(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestJsonContext<(), database::NetnsOption>,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    if cfg!(not(target_os = "linux")) {
+        return generic::ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "network namespaces are only supported on linux",
+        )
+        .into_response();
+    }
+    match database::set_netns(
+        &ctx.manager.get_database(),
+        service.get_name().to_string(),
+        ctx.body.0,
+    )
+    .await
+    {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Delete Netns: DELETE ../service/netns
+// Created namespaces and interfaces are left in place
+pub(crate) async fn delete_netns(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    match database::delete_netns(&ctx.manager.get_database(), service.get_name().to_string()).await
+    {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
     }
 }
 
@@ -122,8 +453,15 @@ where
 }
 
 // Get Config: GET ../service/config
-pub(crate) async fn get_config(ctx: generic::RequestRawBodyContext) -> impl IntoResponse {
-    let config = ctx.manager.get_service().get_config().await;
+pub(crate) async fn get_config(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let config = service.get_config().await;
     generic::GenericResponse::new(StatusCode::OK, config).into_response()
 }
 
@@ -164,8 +502,130 @@ pub(crate) async fn set_auto_start(
     }
 }
 
+// Get Restart Policy: GET ../service/restart_policy
+pub(crate) async fn get_restart_policy(ctx: generic::RequestRawBodyContext) -> impl IntoResponse {
+    match database::get_restart_policy(&ctx.manager.get_database()).await {
+        Ok(v) => generic::GenericResponse::new(StatusCode::OK, v).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Set Restart Policy: PUT ../service/restart_policy
+pub(crate) async fn set_restart_policy(
+    ctx: generic::RequestJsonContext<(), database::RestartPolicy>,
+) -> impl IntoResponse {
+    match database::set_restart_policy(&ctx.manager.get_database(), ctx.body.0).await {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Get Launch Option: GET ../service/launch_option
+pub(crate) async fn get_launch_option(ctx: generic::RequestRawBodyContext) -> impl IntoResponse {
+    match database::get_launch_option(&ctx.manager.get_database()).await {
+        Ok(v) => generic::GenericResponse::new(StatusCode::OK, v).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Set Launch Option: PUT ../service/launch_option
+// Default env, args and working dir for every config
+pub(crate) async fn set_launch_option(
+    ctx: generic::RequestJsonContext<(), database::LaunchOption>,
+) -> impl IntoResponse {
+    match database::set_launch_option(&ctx.manager.get_database(), ctx.body.0).await {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Get Ready Timeout: GET ../service/ready_timeout
+pub(crate) async fn get_ready_timeout(ctx: generic::RequestRawBodyContext) -> impl IntoResponse {
+    match database::get_ready_timeout(&ctx.manager.get_database()).await {
+        Ok(v) => generic::GenericResponse::new(StatusCode::OK, v).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ReadyTimeoutRequestBody {
+    timeout: u64,
+}
+
+// Set Ready Timeout: PUT ../service/ready_timeout
+pub(crate) async fn set_ready_timeout(
+    ctx: generic::RequestJsonContext<(), ReadyTimeoutRequestBody>,
+) -> impl IntoResponse {
+    match database::set_ready_timeout(&ctx.manager.get_database(), ctx.body.0.timeout).await {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Get Stat Retention: GET ../service/stat_retention
+pub(crate) async fn get_stat_retention(ctx: generic::RequestRawBodyContext) -> impl IntoResponse {
+    match database::get_stat_retention(&ctx.manager.get_database()).await {
+        Ok(v) => generic::GenericResponse::new(StatusCode::OK, v).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Set Stat Retention: PUT ../service/stat_retention
+// body: {minute, hour, day} (day), applied at the next hourly prune
+pub(crate) async fn set_stat_retention(
+    ctx: generic::RequestJsonContext<(), database::StatRetention>,
+) -> impl IntoResponse {
+    match database::set_stat_retention(&ctx.manager.get_database(), ctx.body.0).await {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct TrafficTotalResponse {
+    global: Option<database::TrafficTotal>, // None if nothing is counted yet
+    configs: Vec<database::TrafficTotal>,
+}
+
+// Get Traffic Total: GET ../service/traffic_total
+// Lifetime traffic of all instances and of each config, kept across core and manager restarts
+pub(crate) async fn get_traffic_total(ctx: generic::RequestRawBodyContext) -> impl IntoResponse {
+    let db = ctx.manager.get_database();
+    let global =
+        match database::get_traffic_total(&db, database::GLOBAL_TRAFFIC_TOTAL.to_string()).await {
+            Ok(v) => v,
+            Err(e) => return generic::db_error_to_http_response(e).into_response(),
+        };
+    match database::list_config_traffic_total(&db).await {
+        Ok(configs) => {
+            generic::GenericResponse::new(StatusCode::OK, TrafficTotalResponse { global, configs })
+                .into_response()
+        }
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Reset Traffic Total: DELETE ../service/traffic_total
+// Reset the global totals only, the config totals are reset by DELETE ../config/:id/traffic_total
+pub(crate) async fn reset_traffic_total(ctx: generic::RequestRawBodyContext) -> impl IntoResponse {
+    match database::reset_traffic_total(
+        &ctx.manager.get_database(),
+        database::GLOBAL_TRAFFIC_TOTAL.to_string(),
+        chrono::Utc::now().timestamp_millis(),
+    )
+    .await
+    {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
 #[derive(serde::Serialize)]
 pub(crate) struct StatusResponse {
+    state: service::ServiceState,
+    state_time: u64, // unix timestamp (s)
+    last_exit_reason: String,
+    last_exit_time: u64, // unix timestamp (s)
     is_running: bool,
     running_config: String,
     core_version: String,
@@ -175,59 +635,546 @@ pub(crate) struct StatusResponse {
     download_traffic: u64,
     upload_speed: u64,
     download_speed: u64,
+    stats_live: bool, // clash api streams are connected, otherwise the stats above are stale
+    process_cpu_usage: f64, // %
+    process_memory: u64, // B, RSS
+    process_threads: u64,
+    process_fds: u64,
+    process_uptime: u64, // s
+    restart_count: u64,
+    is_restarting: bool,
+    last_failure: String,
+    confirm_config: String,
+    confirm_remaining: u64, // s
 }
 
 // Set Status: (Websocket) ../service/status
 pub(crate) async fn get_status(
+    instance: generic::ServiceInstance,
     ws: axum::extract::ws::WebSocketUpgrade,
     state: axum::extract::State<Arc<Manager>>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |mut socket| {
-        let service = state.get_service();
-        async move {
-            let (notify, status) = service.get_status();
-            loop {
-                let response = StatusResponse {
-                    is_running: status.is_running.load(Ordering::Relaxed),
-                    running_config: status.running_config.read().unwrap().clone(),
-                    core_version: status.core_version.read().unwrap().clone(),
-                    memory_usage: status.memory_usage.load(Ordering::Relaxed),
-                    connection_count: status.connection_count.load(Ordering::Relaxed),
-                    upload_traffic: status.upload_traffic.load(Ordering::Relaxed),
-                    download_traffic: status.download_traffic.load(Ordering::Relaxed),
-                    upload_speed: status.upload_speed.load(Ordering::Relaxed),
-                    download_speed: status.download_speed.load(Ordering::Relaxed),
-                };
-                let s = serde_json::json!(response).to_string();
-                if let Err(_) = socket.send(axum::extract::ws::Message::Text(s)).await {
-                    let _ = socket.send(axum::extract::ws::Message::Close(None)).await;
-                    return;
+    let service = match instance.get_service(&state) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    ws.on_upgrade(move |mut socket| async move {
+        let (notify, status) = service.get_status();
+        loop {
+            let response = StatusResponse {
+                state: status.get_state(),
+                state_time: status.state_time.load(Ordering::Relaxed),
+                last_exit_reason: status.last_exit_reason.read().unwrap().clone(),
+                last_exit_time: status.last_exit_time.load(Ordering::Relaxed),
+                is_running: status.is_running.load(Ordering::Relaxed),
+                running_config: status.running_config.read().unwrap().clone(),
+                core_version: status.core_version.read().unwrap().clone(),
+                memory_usage: status.memory_usage.load(Ordering::Relaxed),
+                connection_count: status.connection_count.load(Ordering::Relaxed),
+                upload_traffic: status.upload_traffic.load(Ordering::Relaxed),
+                download_traffic: status.download_traffic.load(Ordering::Relaxed),
+                upload_speed: status.upload_speed.load(Ordering::Relaxed),
+                download_speed: status.download_speed.load(Ordering::Relaxed),
+                stats_live: status.is_stats_live(),
+                process_cpu_usage: status.process_cpu_usage.load(Ordering::Relaxed) as f64 / 100.0,
+                process_memory: status.process_memory.load(Ordering::Relaxed),
+                process_threads: status.process_threads.load(Ordering::Relaxed),
+                process_fds: status.process_fds.load(Ordering::Relaxed),
+                process_uptime: status.process_uptime.load(Ordering::Relaxed),
+                restart_count: status.restart_count.load(Ordering::Relaxed),
+                is_restarting: status.is_restarting.load(Ordering::Relaxed),
+                last_failure: status.last_failure.read().unwrap().clone(),
+                confirm_config: status.confirm_config.read().unwrap().clone(),
+                confirm_remaining: status
+                    .confirm_deadline
+                    .load(Ordering::Relaxed)
+                    .saturating_sub(chrono::Utc::now().timestamp() as u64),
+            };
+            let s = serde_json::json!(response).to_string();
+            if let Err(_) = socket.send(axum::extract::ws::Message::Text(s)).await {
+                let _ = socket.send(axum::extract::ws::Message::Close(None)).await;
+                return;
+            }
+            notify.notified().await;
+        }
+    })
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct ConnectionsResponseBody {
+    total: usize, // before filtering
+    connections: Vec<service::ClashAPIConnection>,
+}
+
+// Connections: GET ../service/connections
+// (params: ?host=&source_ip=&outbound=&rule=&sort=traffic|upload|download|age&order=asc|desc)
+pub(crate) async fn get_connections(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let query = match axum::extract::Query::<service::ConnectionQuery>::try_from_uri(ctx.req.uri())
+    {
+        Ok(q) => q.0,
+        Err(e) => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string())
+                .into_response();
+        }
+    };
+    let (_, status) = service.get_status();
+    let connections = status.connections.read().unwrap().clone();
+    generic::GenericResponse::new(
+        StatusCode::OK,
+        ConnectionsResponseBody {
+            total: connections.len(),
+            connections: query.apply(&connections),
+        },
+    )
+    .into_response()
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct CloseConnectionsResponseBody {
+    closed: Vec<String>, // ids
+}
+
+// Close Connections: DELETE ../service/connections (params: ?host=&source_ip=&outbound=&rule=)
+// Close the matching connections, or all connections without filters
+pub(crate) async fn close_connections(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let query = match axum::extract::Query::<service::ConnectionQuery>::try_from_uri(ctx.req.uri())
+    {
+        Ok(q) => q.0,
+        Err(e) => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string())
+                .into_response();
+        }
+    };
+    // A mistyped filter must not close everything
+    if matches!(ctx.req.uri().query(), Some(q) if !q.is_empty()) && !query.is_filtered() {
+        return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "no valid filter")
+            .into_response();
+    }
+    match service.close_connections(&query).await {
+        Ok(closed) => {
+            generic::GenericResponse::new(StatusCode::OK, CloseConnectionsResponseBody { closed })
+                .into_response()
+        }
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
+    }
+}
+
+// Close Connection: DELETE ../service/connections/:id
+pub(crate) async fn close_connection(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let id = match ctx.path_params.and_then(|mut p| p.0.remove("id")) {
+        Some(id) if id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') => id,
+        _ => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "invalid id")
+                .into_response();
+        }
+    };
+    match service.close_connection(&id).await {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
+    }
+}
+
+// Proxy Groups: GET ../service/proxies
+// Outbound groups of the running core with their members and current selection
+pub(crate) async fn get_proxy_groups(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    match service.get_proxy_groups().await {
+        Ok(groups) => generic::GenericResponse::new(StatusCode::OK, groups).into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct SelectProxyRequestBody {
+    name: String,
+}
+
+// Select Proxy: PUT ../service/proxies/:group
+// Saved per config and restored when the core is started again
+pub(crate) async fn select_proxy(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestJsonContext<HashMap<String, String>, SelectProxyRequestBody>,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let group = match ctx.path_params.and_then(|mut p| p.0.remove("group")) {
+        Some(group) if !group.is_empty() => group,
+        _ => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing group")
+                .into_response();
+        }
+    };
+    let name = ctx.body.0.name;
+    if name.is_empty() {
+        return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing name")
+            .into_response();
+    }
+    match service.select_proxy(&group, &name).await {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
+    }
+}
+
+// Get Clash Mode: GET ../service/mode
+// {mode, mode_list} of the running core
+pub(crate) async fn get_clash_mode(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    match service.get_clash_mode().await {
+        Ok(v) => generic::GenericResponse::new(StatusCode::OK, v).into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct SetClashModeRequestBody {
+    mode: String,
+}
+
+// Set Clash Mode: PUT ../service/mode
+// Saved per config and restored when the core is started again
+pub(crate) async fn set_clash_mode(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestJsonContext<(), SetClashModeRequestBody>,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let mode = ctx.body.0.mode;
+    if mode.is_empty() {
+        return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing mode")
+            .into_response();
+    }
+    match service.set_clash_mode(&mode).await {
+        Ok(mode) => generic::GenericResponse::new(StatusCode::OK, mode).into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct DNSQueryQuery {
+    name: String,
+    #[serde(rename = "type", default = "default_dns_query_type")]
+    query_type: String,
+}
+
+fn default_dns_query_type() -> String {
+    "A".to_string()
+}
+
+// DNS Query: GET ../service/dns/query (params: ?name=<domain>&type=<A|AAAA|...>, default type: A)
+// Resolved by the core's dns
+pub(crate) async fn dns_query(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let query = match axum::extract::Query::<DNSQueryQuery>::try_from_uri(ctx.req.uri()) {
+        Ok(q) => q.0,
+        Err(e) => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string())
+                .into_response();
+        }
+    };
+    if query.name.is_empty() {
+        return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, "missing name")
+            .into_response();
+    }
+    match service.dns_query(&query.name, &query.query_type).await {
+        Ok(v) => generic::GenericResponse::new(StatusCode::OK, v).into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FlushCacheType {
+    Dns,
+    Fakeip,
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct FlushCacheQuery {
+    #[serde(rename = "type")]
+    cache_type: Option<FlushCacheType>,
+}
+
+// Flush Cache: POST ../service/cache/flush (params: ?type=<dns|fakeip>, both without type)
+pub(crate) async fn flush_cache(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let query = match axum::extract::Query::<FlushCacheQuery>::try_from_uri(ctx.req.uri()) {
+        Ok(q) => q.0,
+        Err(e) => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string())
+                .into_response();
+        }
+    };
+    let (dns, fakeip) = match query.cache_type {
+        Some(FlushCacheType::Dns) => (true, false),
+        Some(FlushCacheType::Fakeip) => (false, true),
+        None => (true, true),
+    };
+    match service.flush_cache(dns, fakeip).await {
+        Ok(_) => generic::GenericResponse::new(StatusCode::OK, "success").into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
+    }
+}
+
+// Delay Test: POST ../service/delay_tests
+// body: {outbounds, groups, url, timeout (ms), concurrency}, results are saved with the running config
+pub(crate) async fn test_delay(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestJsonContext<(), service::DelayTestOption>,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let option = ctx.body.0;
+    if let Err(e) = option.check() {
+        return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e).into_response();
+    }
+    match service.test_delay(&option).await {
+        Ok(tests) => generic::GenericResponse::new(StatusCode::OK, tests).into_response(),
+        Err(e) => generic::service_error_to_http_response(e).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ListDelayTestQuery {
+    outbound: Option<String>,
+    since: Option<i64>, // unix timestamp (ms)
+    until: Option<i64>, // unix timestamp (ms)
+    limit: Option<u64>,
+}
+
+// List Delay Test: GET ../service/delay_tests (params: ?outbound=&since=&until=&limit=)
+// The latest results in time order
+pub(crate) async fn list_delay_test(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    const DEFAULT_LIMIT: u64 = 1000;
+    const MAX_LIMIT: u64 = 10000;
+
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let query = match axum::extract::Query::<ListDelayTestQuery>::try_from_uri(ctx.req.uri()) {
+        Ok(q) => q.0,
+        Err(e) => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string())
+                .into_response();
+        }
+    };
+    match database::list_delay_test(
+        &ctx.manager.get_database(),
+        service.get_name().to_string(),
+        query.outbound.filter(|s| !s.is_empty()),
+        query.since,
+        query.until,
+        query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+    )
+    .await
+    {
+        Ok(tests) => generic::GenericResponse::new(StatusCode::OK, tests).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ListStatQuery {
+    #[serde(default = "default_stat_granularity")]
+    granularity: database::StatGranularity,
+    since: Option<i64>, // unix timestamp (ms)
+    until: Option<i64>, // unix timestamp (ms)
+    limit: Option<u64>,
+}
+
+fn default_stat_granularity() -> database::StatGranularity {
+    database::StatGranularity::Minute
+}
+
+// List Stat: GET ../service/stats (params: ?granularity=<minute|hour|day>&since=&until=&limit=)
+// Traffic and resource buckets in time order, the latest ones within limit
+pub(crate) async fn list_stat(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    const DEFAULT_LIMIT: u64 = 1440;
+    const MAX_LIMIT: u64 = 10000;
+
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let query = match axum::extract::Query::<ListStatQuery>::try_from_uri(ctx.req.uri()) {
+        Ok(q) => q.0,
+        Err(e) => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string())
+                .into_response();
+        }
+    };
+    match database::list_stat(
+        &ctx.manager.get_database(),
+        service.get_name().to_string(),
+        query.granularity,
+        query.since,
+        query.until,
+        query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+    )
+    .await
+    {
+        Ok(stats) => generic::GenericResponse::new(StatusCode::OK, stats).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct DelaySummaryQuery {
+    since: Option<i64>, // unix timestamp (ms)
+}
+
+// Delay Summary: GET ../service/delay_tests/summary (params: ?since=)
+// Per outbound count, success and delay statistics, the fastest first
+pub(crate) async fn get_delay_summary(
+    instance: generic::ServiceInstance,
+    ctx: generic::RequestRawBodyContext,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&ctx.manager) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let query = match axum::extract::Query::<DelaySummaryQuery>::try_from_uri(ctx.req.uri()) {
+        Ok(q) => q.0,
+        Err(e) => {
+            return generic::ErrorResponse::new(StatusCode::BAD_REQUEST, e.to_string())
+                .into_response();
+        }
+    };
+    match database::summarize_delay_test(
+        &ctx.manager.get_database(),
+        service.get_name().to_string(),
+        query.since,
+    )
+    .await
+    {
+        Ok(summary) => generic::GenericResponse::new(StatusCode::OK, summary).into_response(),
+        Err(e) => generic::db_error_to_http_response(e).into_response(),
+    }
+}
+
+// Connections: (Websocket) ../service/connections/stream (params: same as ../service/connections)
+// Sends {added, updated, removed} on change, the first message adds all matched connections
+pub(crate) async fn stream_connections(
+    instance: generic::ServiceInstance,
+    query: axum::extract::Query<service::ConnectionQuery>,
+    ws: axum::extract::ws::WebSocketUpgrade,
+    state: axum::extract::State<Arc<Manager>>,
+) -> impl IntoResponse {
+    let service = match instance.get_service(&state) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let query = query.0;
+    ws.on_upgrade(move |mut socket| async move {
+        let (notify, status) = service.get_status();
+        let mut snapshot = Arc::new(Vec::new());
+        let mut previous = Vec::new();
+        let mut first = true;
+        loop {
+            // Status also changes on speed and memory updates
+            let notified = notify.notified();
+            let connections = status.connections.read().unwrap().clone();
+            if first || !Arc::ptr_eq(&snapshot, &connections) {
+                let current = query.apply(&connections);
+                let diff = service::ConnectionDiff::new(&previous, &current);
+                if first || !diff.is_empty() {
+                    let s = serde_json::json!(diff).to_string();
+                    if socket
+                        .send(axum::extract::ws::Message::Text(s))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
                 }
-                notify.notified().await;
+                first = false;
+                snapshot = connections;
+                previous = current;
             }
+            notified.await;
         }
+        let _ = socket.send(axum::extract::ws::Message::Close(None)).await;
     })
 }
 
 // Log: (Websocket) ../service/log
 pub(crate) async fn get_log(
+    instance: generic::ServiceInstance,
     ws: axum::extract::ws::WebSocketUpgrade,
     state: axum::extract::State<Arc<Manager>>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |mut socket| {
-        let service = state.get_service();
-        async move {
-            let log_queue_listener = service.log_queue_listener();
-            let (sender, mut receiver) = mpsc::channel(1);
-            tokio::spawn(async move {
-                log_queue_listener.listen(sender).await;
-            });
-            while let Some(s) = receiver.recv().await {
-                if let Err(_) = socket.send(axum::extract::ws::Message::Text(s)).await {
-                    break;
-                }
+    let service = match instance.get_service(&state) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    ws.on_upgrade(move |mut socket| async move {
+        let log_queue_listener = service.log_queue_listener();
+        let (sender, mut receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            log_queue_listener.listen(sender).await;
+        });
+        while let Some(s) = receiver.recv().await {
+            if let Err(_) = socket.send(axum::extract::ws::Message::Text(s)).await {
+                break;
             }
-            let _ = socket.send(axum::extract::ws::Message::Close(None)).await;
         }
+        let _ = socket.send(axum::extract::ws::Message::Close(None)).await;
     })
 }
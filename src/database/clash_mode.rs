@@ -0,0 +1,68 @@
+use sea_orm::{entity::prelude::*, sea_query::OnConflict, IntoActiveModel};
+use serde::{Deserialize, Serialize};
+
+// Clash mode of a config (rule, global, direct, ...), re-applied when its core is ready
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "clash_mode")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub config_id: String,
+    pub mode: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+// Set Clash Mode
+pub(crate) async fn set_clash_mode(
+    conn: &sea_orm::DatabaseConnection,
+    mode: Model,
+) -> Result<(), super::Error> {
+    if mode.config_id.is_empty() {
+        return Err(super::Error::ConfigMissingID);
+    }
+    if mode.mode.is_empty() {
+        return Err(super::Error::CustomErr("empty mode".to_string()));
+    }
+    Entity::insert(mode.into_active_model())
+        .on_conflict(
+            OnConflict::column(Column::ConfigId)
+                .update_column(Column::Mode)
+                .to_owned(),
+        )
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    Ok(())
+}
+
+// Get Clash Mode
+pub(crate) async fn get_clash_mode(
+    conn: &sea_orm::DatabaseConnection,
+    config_id: String,
+) -> Result<Option<Model>, super::Error> {
+    if config_id.is_empty() {
+        return Err(super::Error::ConfigMissingID);
+    }
+    Entity::find_by_id(config_id)
+        .one(conn)
+        .await
+        .map_err(super::Error::DBError)
+}
+
+// Delete Clash Mode
+pub(crate) async fn delete_clash_mode(
+    conn: &sea_orm::DatabaseConnection,
+    config_id: String,
+) -> Result<(), super::Error> {
+    if config_id.is_empty() {
+        return Err(super::Error::ConfigMissingID);
+    }
+    Entity::delete_by_id(config_id)
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    Ok(())
+}
@@ -109,3 +109,235 @@ pub(crate) async fn get_auto_start(
         None => Ok(false),
     }
 }
+
+const KEY_RESTART_POLICY: &str = "restart_policy";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RestartMode {
+    Never,
+    OnFailure,
+    Always,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct RestartPolicy {
+    pub(crate) mode: RestartMode,
+    pub(crate) max_retries: u32,     // 0: unlimited
+    pub(crate) initial_backoff: u64, // s
+    pub(crate) max_backoff: u64,     // s
+    pub(crate) reset_window: u64,    // s
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            mode: RestartMode::Never,
+            max_retries: 5,
+            initial_backoff: 1,
+            max_backoff: 60,
+            reset_window: 300,
+        }
+    }
+}
+
+impl RestartPolicy {
+    // Backoff before the given restart attempt (starting at 1)
+    pub(crate) fn backoff(&self, attempt: u32) -> std::time::Duration {
+        let exp = attempt.saturating_sub(1).min(31);
+        let secs = self
+            .initial_backoff
+            .saturating_mul(1u64 << exp)
+            .min(self.max_backoff.max(self.initial_backoff));
+        std::time::Duration::from_secs(secs)
+    }
+}
+
+// Set Restart Policy
+pub(crate) async fn set_restart_policy(
+    conn: &sea_orm::DatabaseConnection,
+    policy: RestartPolicy,
+) -> Result<(), super::Error> {
+    if policy.initial_backoff == 0 {
+        return Err(super::Error::CustomErr(
+            "initial_backoff must be greater than 0".to_string(),
+        ));
+    }
+    if policy.max_backoff < policy.initial_backoff {
+        return Err(super::Error::CustomErr(
+            "max_backoff must not be less than initial_backoff".to_string(),
+        ));
+    }
+    let value = serde_json::to_value(policy).map_err(|e| super::Error::CustomErr(e.to_string()))?;
+    set_value(conn, KEY_RESTART_POLICY, value).await
+}
+
+// Get Restart Policy
+pub(crate) async fn get_restart_policy(
+    conn: &sea_orm::DatabaseConnection,
+) -> Result<RestartPolicy, super::Error> {
+    match get_value(conn, KEY_RESTART_POLICY).await? {
+        Some(v) => serde_json::from_value(v).map_err(|e| super::Error::CustomErr(e.to_string())),
+        None => Ok(RestartPolicy::default()),
+    }
+}
+
+const KEY_READY_TIMEOUT: &str = "ready_timeout";
+
+const DEFAULT_READY_TIMEOUT: u64 = 30; // s
+
+// Set Ready Timeout
+pub(crate) async fn set_ready_timeout(
+    conn: &sea_orm::DatabaseConnection,
+    timeout: u64,
+) -> Result<(), super::Error> {
+    if timeout == 0 {
+        return Err(super::Error::CustomErr(
+            "timeout must be greater than 0".to_string(),
+        ));
+    }
+    set_value(conn, KEY_READY_TIMEOUT, timeout.into()).await
+}
+
+// Get Ready Timeout
+pub(crate) async fn get_ready_timeout(
+    conn: &sea_orm::DatabaseConnection,
+) -> Result<u64, super::Error> {
+    match get_value(conn, KEY_READY_TIMEOUT).await? {
+        Some(serde_json::Value::Number(n)) => Ok(n.as_u64().unwrap_or(DEFAULT_READY_TIMEOUT)),
+        _ => Ok(DEFAULT_READY_TIMEOUT),
+    }
+}
+
+const KEY_STAT_RETENTION: &str = "stat_retention";
+
+// How long the stat buckets of each granularity are kept
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct StatRetention {
+    pub(crate) minute: u64, // day
+    pub(crate) hour: u64,   // day
+    pub(crate) day: u64,    // day
+}
+
+impl Default for StatRetention {
+    fn default() -> Self {
+        Self {
+            minute: 2,
+            hour: 90,
+            day: 730,
+        }
+    }
+}
+
+impl StatRetention {
+    pub(crate) fn get(&self, granularity: super::StatGranularity) -> std::time::Duration {
+        let days = match granularity {
+            super::StatGranularity::Minute => self.minute,
+            super::StatGranularity::Hour => self.hour,
+            super::StatGranularity::Day => self.day,
+        };
+        std::time::Duration::from_secs(days * 24 * 60 * 60)
+    }
+}
+
+// Set Stat Retention
+pub(crate) async fn set_stat_retention(
+    conn: &sea_orm::DatabaseConnection,
+    retention: StatRetention,
+) -> Result<(), super::Error> {
+    if retention.minute == 0 || retention.hour == 0 || retention.day == 0 {
+        return Err(super::Error::CustomErr(
+            "retention must be greater than 0".to_string(),
+        ));
+    }
+    let value =
+        serde_json::to_value(retention).map_err(|e| super::Error::CustomErr(e.to_string()))?;
+    set_value(conn, KEY_STAT_RETENTION, value).await
+}
+
+// Get Stat Retention
+pub(crate) async fn get_stat_retention(
+    conn: &sea_orm::DatabaseConnection,
+) -> Result<StatRetention, super::Error> {
+    match get_value(conn, KEY_STAT_RETENTION).await? {
+        Some(v) => serde_json::from_value(v).map_err(|e| super::Error::CustomErr(e.to_string())),
+        None => Ok(StatRetention::default()),
+    }
+}
+
+async fn set_value(
+    conn: &sea_orm::DatabaseConnection,
+    key: &'static str,
+    value: serde_json::Value,
+) -> Result<(), super::Error> {
+    super::set_kv(
+        conn,
+        Kv {
+            key: key.to_string(),
+            value,
+        },
+    )
+    .await
+    .map(|_| ())
+}
+
+async fn get_value(
+    conn: &sea_orm::DatabaseConnection,
+    key: &'static str,
+) -> Result<Option<serde_json::Value>, super::Error> {
+    let kv = KvEntity::find_by_id(key)
+        .one(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+
+    Ok(kv.map(|kv| kv.value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_restart_policy_backoff() {
+        let policy = RestartPolicy {
+            initial_backoff: 2,
+            max_backoff: 60,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_secs(2));
+        assert_eq!(policy.backoff(2), Duration::from_secs(4));
+        assert_eq!(policy.backoff(5), Duration::from_secs(32));
+        // Capped at max_backoff
+        assert_eq!(policy.backoff(6), Duration::from_secs(60));
+        assert_eq!(policy.backoff(20), Duration::from_secs(60));
+        // The exponent saturates instead of overflowing the shift
+        assert_eq!(policy.backoff(32), Duration::from_secs(60));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(60));
+        // Attempt 0 is treated as the first one
+        assert_eq!(policy.backoff(0), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_restart_policy_backoff_saturation() {
+        let policy = RestartPolicy {
+            initial_backoff: u64::MAX / 2,
+            max_backoff: u64::MAX,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_secs(u64::MAX / 2));
+        assert_eq!(policy.backoff(2), Duration::from_secs(u64::MAX - 1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(u64::MAX));
+        assert_eq!(policy.backoff(40), Duration::from_secs(u64::MAX));
+        // A max_backoff below initial_backoff keeps the initial one
+        let policy = RestartPolicy {
+            initial_backoff: 10,
+            max_backoff: 5,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(3), Duration::from_secs(10));
+    }
+}
@@ -135,6 +135,24 @@ pub(crate) async fn delete_config(
                     .map_err(|e| super::Error::DBError(e))?;
             }
 
+            super::LaunchOptionEntity::delete_by_id(&id)
+                .exec(tx)
+                .await
+                .map_err(super::Error::DBError)?;
+            super::ProxySelectionEntity::delete_many()
+                .filter(super::proxy_selection::Column::ConfigId.eq(&id))
+                .exec(tx)
+                .await
+                .map_err(super::Error::DBError)?;
+            super::ClashModeEntity::delete_by_id(&id)
+                .exec(tx)
+                .await
+                .map_err(super::Error::DBError)?;
+            super::TrafficTotalEntity::delete_by_id(&id)
+                .exec(tx)
+                .await
+                .map_err(super::Error::DBError)?;
+
             Ok(())
         })
     })
@@ -153,6 +171,26 @@ pub(crate) async fn bulk_delete_config(
     if ids.len() == 0 {
         return Ok(());
     }
+    super::LaunchOptionEntity::delete_many()
+        .filter(super::launch_option::Column::ConfigId.is_in(ids.clone()))
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    super::ProxySelectionEntity::delete_many()
+        .filter(super::proxy_selection::Column::ConfigId.is_in(ids.clone()))
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    super::ClashModeEntity::delete_many()
+        .filter(super::clash_mode::Column::ConfigId.is_in(ids.clone()))
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    super::TrafficTotalEntity::delete_many()
+        .filter(super::traffic_total::Column::Id.is_in(ids.clone()))
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
     let mut filter: Option<sea_orm::sea_query::SimpleExpr> = None;
     for id in ids {
         filter = match filter {
@@ -1,5 +1,9 @@
 use std::{
     ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 
@@ -8,6 +12,7 @@ use sea_orm::{ConnectionTrait, Schema};
 #[derive(Debug, Clone)]
 pub(crate) struct Database {
     connection: sea_orm::DatabaseConnection,
+    errors: Arc<AtomicU64>, // failed statements, for the metrics
 }
 
 impl Database {
@@ -23,9 +28,21 @@ impl Database {
             .sqlx_logging(true)
             .sqlx_logging_level(log::LevelFilter::Trace)
             .set_schema_search_path("my_schema");
-        let conn = sea_orm::Database::connect(options).await?;
+        let mut conn = sea_orm::Database::connect(options).await?;
+        let errors = Arc::new(AtomicU64::new(0));
+        {
+            let errors = errors.clone();
+            conn.set_metric_callback(move |info| {
+                if info.failed {
+                    errors.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
 
-        let s = Self { connection: conn };
+        let s = Self {
+            connection: conn,
+            errors,
+        };
 
         s.initialize()
             .await
@@ -34,6 +51,10 @@ impl Database {
         Ok(s)
     }
 
+    pub(crate) fn error_count(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
     pub(crate) async fn close(self) -> Result<(), sea_orm::DbErr> {
         self.connection.close().await
     }
@@ -53,14 +74,110 @@ impl Database {
         let mut kv_stmt_builder = schema.create_table_from_entity(super::KvEntity);
         kv_stmt_builder.if_not_exists();
         let kv_stmt = builder.build(&kv_stmt_builder);
+        // Instance
+        let mut instance_stmt_builder = schema.create_table_from_entity(super::InstanceEntity);
+        instance_stmt_builder.if_not_exists();
+        let instance_stmt = builder.build(&instance_stmt_builder);
+        // Service Run
+        let mut service_run_stmt_builder = schema.create_table_from_entity(super::ServiceRunEntity);
+        service_run_stmt_builder.if_not_exists();
+        let service_run_stmt = builder.build(&service_run_stmt_builder);
+        // Launch Option
+        let mut launch_option_stmt_builder =
+            schema.create_table_from_entity(super::LaunchOptionEntity);
+        launch_option_stmt_builder.if_not_exists();
+        let launch_option_stmt = builder.build(&launch_option_stmt_builder);
+        // Process Limit
+        let mut process_limit_stmt_builder =
+            schema.create_table_from_entity(super::ProcessLimitEntity);
+        process_limit_stmt_builder.if_not_exists();
+        let process_limit_stmt = builder.build(&process_limit_stmt_builder);
+        // Netns
+        let mut netns_stmt_builder = schema.create_table_from_entity(super::NetnsEntity);
+        netns_stmt_builder.if_not_exists();
+        let netns_stmt = builder.build(&netns_stmt_builder);
+        // Proxy Selection
+        let mut proxy_selection_stmt_builder =
+            schema.create_table_from_entity(super::ProxySelectionEntity);
+        proxy_selection_stmt_builder.if_not_exists();
+        let proxy_selection_stmt = builder.build(&proxy_selection_stmt_builder);
+        // Delay Test
+        let mut delay_test_stmt_builder = schema.create_table_from_entity(super::DelayTestEntity);
+        delay_test_stmt_builder.if_not_exists();
+        let delay_test_stmt = builder.build(&delay_test_stmt_builder);
+        // Clash Mode
+        let mut clash_mode_stmt_builder = schema.create_table_from_entity(super::ClashModeEntity);
+        clash_mode_stmt_builder.if_not_exists();
+        let clash_mode_stmt = builder.build(&clash_mode_stmt_builder);
+        // Stat
+        let mut stat_stmt_builder = schema.create_table_from_entity(super::StatEntity);
+        stat_stmt_builder.if_not_exists();
+        let stat_stmt = builder.build(&stat_stmt_builder);
+        // Traffic Total
+        let mut traffic_total_stmt_builder =
+            schema.create_table_from_entity(super::TrafficTotalEntity);
+        traffic_total_stmt_builder.if_not_exists();
+        let traffic_total_stmt = builder.build(&traffic_total_stmt_builder);
         //
-        let (config_result, script_result, kv_result) = tokio::join!(
+        let (
+            config_result,
+            script_result,
+            kv_result,
+            instance_result,
+            service_run_result,
+            launch_option_result,
+            process_limit_result,
+            netns_result,
+            proxy_selection_result,
+            delay_test_result,
+            clash_mode_result,
+            stat_result,
+            traffic_total_result,
+        ) = tokio::join!(
             self.connection.execute(config_stmt),
             self.connection.execute(kv_stmt),
             self.connection.execute(script_stmt),
+            self.connection.execute(instance_stmt),
+            self.connection.execute(service_run_stmt),
+            self.connection.execute(launch_option_stmt),
+            self.connection.execute(process_limit_stmt),
+            self.connection.execute(netns_stmt),
+            self.connection.execute(proxy_selection_stmt),
+            self.connection.execute(delay_test_stmt),
+            self.connection.execute(clash_mode_stmt),
+            self.connection.execute(stat_stmt),
+            self.connection.execute(traffic_total_stmt),
         );
-        match (&config_result, &script_result, &kv_result) {
-            (Ok(_), Ok(_), Ok(_)) => Ok(()),
+        match (
+            &config_result,
+            &script_result,
+            &kv_result,
+            &instance_result,
+            &service_run_result,
+            &launch_option_result,
+            &process_limit_result,
+            &netns_result,
+            &proxy_selection_result,
+            &delay_test_result,
+            &clash_mode_result,
+            &stat_result,
+            &traffic_total_result,
+        ) {
+            (
+                Ok(_),
+                Ok(_),
+                Ok(_),
+                Ok(_),
+                Ok(_),
+                Ok(_),
+                Ok(_),
+                Ok(_),
+                Ok(_),
+                Ok(_),
+                Ok(_),
+                Ok(_),
+                Ok(_),
+            ) => Ok(()),
             _ => {
                 let mut s = format!("failed to create table: ");
                 if let Err(e) = config_result {
@@ -72,6 +189,36 @@ impl Database {
                 if let Err(e) = kv_result {
                     s.push_str(&format!("kv table: {}, ", e))
                 }
+                if let Err(e) = instance_result {
+                    s.push_str(&format!("instance table: {}, ", e))
+                }
+                if let Err(e) = service_run_result {
+                    s.push_str(&format!("service run table: {}, ", e))
+                }
+                if let Err(e) = launch_option_result {
+                    s.push_str(&format!("launch option table: {}, ", e))
+                }
+                if let Err(e) = process_limit_result {
+                    s.push_str(&format!("process limit table: {}, ", e))
+                }
+                if let Err(e) = netns_result {
+                    s.push_str(&format!("netns table: {}, ", e))
+                }
+                if let Err(e) = proxy_selection_result {
+                    s.push_str(&format!("proxy selection table: {}, ", e))
+                }
+                if let Err(e) = delay_test_result {
+                    s.push_str(&format!("delay test table: {}, ", e))
+                }
+                if let Err(e) = clash_mode_result {
+                    s.push_str(&format!("clash mode table: {}, ", e))
+                }
+                if let Err(e) = stat_result {
+                    s.push_str(&format!("stat table: {}, ", e))
+                }
+                if let Err(e) = traffic_total_result {
+                    s.push_str(&format!("traffic total table: {}, ", e))
+                }
                 s.pop();
                 s.pop();
                 Err(s)
@@ -0,0 +1,124 @@
+use sea_orm::{
+    entity::prelude::*,
+    sea_query::{Expr, Func, SimpleExpr},
+    FromQueryResult, IntoActiveModel, QueryOrder, QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::common;
+
+// Result of one outbound delay test
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "delay_test")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: String,
+    pub instance: String,
+    pub config_id: String,
+    pub outbound: String,
+    pub url: String,
+    pub delay: Option<u32>,    // ms, None if failed
+    pub error: Option<String>, // failure message
+    pub time: i64,             // unix timestamp (ms)
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+// Per outbound statistics of the delay tests
+#[derive(Debug, Clone, FromQueryResult, Serialize)]
+pub(crate) struct DelaySummary {
+    pub(crate) outbound: String,
+    pub(crate) count: i64,
+    pub(crate) success: i64,
+    pub(crate) avg_delay: Option<f64>, // ms, of the successful tests
+    pub(crate) min_delay: Option<u32>, // ms
+    pub(crate) max_delay: Option<u32>, // ms
+    pub(crate) last_time: i64,         // unix timestamp (ms)
+}
+
+// Add Delay Tests, return them with the generated ids
+pub(crate) async fn add_delay_test(
+    conn: &sea_orm::DatabaseConnection,
+    mut tests: Vec<Model>,
+) -> Result<Vec<Model>, super::Error> {
+    if tests.is_empty() {
+        return Ok(tests);
+    }
+    for test in tests.iter_mut().filter(|test| test.id.is_empty()) {
+        test.id = common::random_uuid().replace('-', "");
+    }
+    Entity::insert_many(
+        tests
+            .iter()
+            .cloned()
+            .map(IntoActiveModel::into_active_model),
+    )
+    .exec(conn)
+    .await
+    .map_err(super::Error::DBError)?;
+    Ok(tests)
+}
+
+// List Delay Tests, the latest `limit` ones in time order
+pub(crate) async fn list_delay_test(
+    conn: &sea_orm::DatabaseConnection,
+    instance: String,
+    outbound: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+    limit: u64,
+) -> Result<Vec<Model>, super::Error> {
+    let mut select = Entity::find().filter(Column::Instance.eq(instance));
+    if let Some(outbound) = outbound {
+        select = select.filter(Column::Outbound.eq(outbound));
+    }
+    if let Some(since) = since {
+        select = select.filter(Column::Time.gte(since));
+    }
+    if let Some(until) = until {
+        select = select.filter(Column::Time.lt(until));
+    }
+    let mut tests = select
+        .order_by_desc(Column::Time)
+        .limit(limit)
+        .all(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    tests.reverse();
+    Ok(tests)
+}
+
+// Summarize Delay Tests per outbound, the fastest first
+pub(crate) async fn summarize_delay_test(
+    conn: &sea_orm::DatabaseConnection,
+    instance: String,
+    since: Option<i64>,
+) -> Result<Vec<DelaySummary>, super::Error> {
+    let mut select = Entity::find().filter(Column::Instance.eq(instance));
+    if let Some(since) = since {
+        select = select.filter(Column::Time.gte(since));
+    }
+    select
+        .select_only()
+        .column(Column::Outbound)
+        .column_as(Expr::col(Column::Id).count(), "count")
+        // COUNT skips NULL, the failed tests
+        .column_as(Expr::col(Column::Delay).count(), "success")
+        .column_as(
+            SimpleExpr::from(Func::avg(Expr::col(Column::Delay))),
+            "avg_delay",
+        )
+        .column_as(Expr::col(Column::Delay).min(), "min_delay")
+        .column_as(Expr::col(Column::Delay).max(), "max_delay")
+        .column_as(Expr::col(Column::Time).max(), "last_time")
+        .group_by(Column::Outbound)
+        .order_by_asc(Expr::cust("avg_delay IS NULL"))
+        .order_by_asc(Expr::cust("avg_delay"))
+        .into_model::<DelaySummary>()
+        .all(conn)
+        .await
+        .map_err(super::Error::DBError)
+}
@@ -17,6 +17,12 @@ pub(crate) enum Error {
     // Kv
     KvMissingKey,
     KvNotFound(String), // Key
+    // Instance
+    InstanceMissingName,
+    InstanceInvalidName(String),  // Name
+    InstanceReservedName(String), // Name
+    InstanceDuplicateName,
+    InstanceNotFound(String), // Name
     //
     CustomErr(String),
 }
@@ -37,6 +43,11 @@ impl fmt::Debug for Error {
             Self::ScriptNotFound(id) => write!(f, "script: not found, id: {}", id),
             Self::KvMissingKey => write!(f, "kv: missing key"),
             Self::KvNotFound(key) => write!(f, "kv: not found, key: {}", key),
+            Self::InstanceMissingName => write!(f, "instance: missing name"),
+            Self::InstanceInvalidName(name) => write!(f, "instance: invalid name: {}", name),
+            Self::InstanceReservedName(name) => write!(f, "instance: reserved name: {}", name),
+            Self::InstanceDuplicateName => write!(f, "instance: duplicate name"),
+            Self::InstanceNotFound(name) => write!(f, "instance: not found, name: {}", name),
             Self::CustomErr(e) => write!(f, "{}", e),
         }
     }
@@ -58,6 +69,11 @@ impl fmt::Display for Error {
             Self::ScriptNotFound(id) => write!(f, "script: not found, id: {}", id),
             Self::KvMissingKey => write!(f, "kv: missing key"),
             Self::KvNotFound(key) => write!(f, "kv: not found, key: {}", key),
+            Self::InstanceMissingName => write!(f, "instance: missing name"),
+            Self::InstanceInvalidName(name) => write!(f, "instance: invalid name: {}", name),
+            Self::InstanceReservedName(name) => write!(f, "instance: reserved name: {}", name),
+            Self::InstanceDuplicateName => write!(f, "instance: duplicate name"),
+            Self::InstanceNotFound(name) => write!(f, "instance: not found, name: {}", name),
             Self::CustomErr(e) => write!(f, "{}", e),
         }
     }
@@ -0,0 +1,187 @@
+use sea_orm::{
+    entity::prelude::*, ActiveModelTrait, ActiveValue, IntoActiveModel, TransactionError,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+// The default instance keeps using the legacy settings:
+// core path and auto start in kv, active config flag, script run type
+pub(crate) const DEFAULT_INSTANCE: &str = "default";
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "instance")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub name: String,
+    pub core_path: Option<String>, // fall back to the global core path
+    pub config_id: Option<String>,
+    pub auto_start: bool,
+    pub before_start_script: Option<String>, // Script ID
+    pub after_start_script: Option<String>,  // Script ID
+    pub before_close_script: Option<String>, // Script ID
+    pub after_close_script: Option<String>,  // Script ID
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+fn check_instance_name(name: &str) -> Result<(), super::Error> {
+    if name.is_empty() {
+        return Err(super::Error::InstanceMissingName);
+    }
+    if name == DEFAULT_INSTANCE {
+        return Err(super::Error::InstanceReservedName(name.to_string()));
+    }
+    // The name is used in file names
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(super::Error::InstanceInvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+// Add Instance
+pub(crate) async fn add_instance(
+    conn: &sea_orm::DatabaseConnection,
+    instance: Model,
+) -> Result<Model, super::Error> {
+    check_instance_name(&instance.name)?;
+    let result = Entity::find_by_id(&instance.name)
+        .one(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    if result.is_some() {
+        return Err(super::Error::InstanceDuplicateName);
+    }
+    instance
+        .into_active_model()
+        .insert(conn)
+        .await
+        .map_err(super::Error::DBError)
+}
+
+// Get Instance
+pub(crate) async fn get_instance(
+    conn: &sea_orm::DatabaseConnection,
+    name: String,
+) -> Result<Model, super::Error> {
+    if name.is_empty() {
+        return Err(super::Error::InstanceMissingName);
+    }
+    Entity::find_by_id(&name)
+        .one(conn)
+        .await
+        .map_err(super::Error::DBError)
+        .and_then(|result| result.ok_or(super::Error::InstanceNotFound(name)))
+}
+
+// Modify Instance
+pub(crate) async fn modify_instance(
+    conn: &sea_orm::DatabaseConnection,
+    name: String,
+    mut instance: ActiveModel,
+) -> Result<Model, super::Error> {
+    if name.is_empty() {
+        return Err(super::Error::InstanceMissingName);
+    }
+    instance.name = ActiveValue::set(name);
+    instance.update(conn).await.map_err(super::Error::DBError)
+}
+
+// Delete Instance
+// A new instance with the same name starts without history
+pub(crate) async fn delete_instance(
+    conn: &sea_orm::DatabaseConnection,
+    name: String,
+) -> Result<(), super::Error> {
+    if name.is_empty() {
+        return Err(super::Error::InstanceMissingName);
+    }
+    conn.transaction::<_, (), super::Error>(|tx| {
+        Box::pin(async move {
+            if Entity::find_by_id(&name)
+                .one(tx)
+                .await
+                .map_err(super::Error::DBError)?
+                .is_none()
+            {
+                return Err(super::Error::InstanceNotFound(name));
+            }
+            super::ProcessLimitEntity::delete_by_id(&name)
+                .exec(tx)
+                .await
+                .map_err(super::Error::DBError)?;
+            super::NetnsEntity::delete_by_id(&name)
+                .exec(tx)
+                .await
+                .map_err(super::Error::DBError)?;
+            delete_instance_history(tx, &name).await?;
+            Entity::delete_by_id(&name)
+                .exec(tx)
+                .await
+                .map_err(super::Error::DBError)?;
+            Ok(())
+        })
+    })
+    .await
+    .map_err(|e| match e {
+        TransactionError::Connection(e) => super::Error::DBError(e),
+        TransactionError::Transaction(e) => e,
+    })
+}
+
+// Delete Instance History: stats, delay tests and runs
+// Also called after the service is closed, which writes its last ones
+pub(crate) async fn delete_instance_history<C: ConnectionTrait>(
+    conn: &C,
+    name: &str,
+) -> Result<(), super::Error> {
+    super::StatEntity::delete_many()
+        .filter(super::stat::Column::Instance.eq(name))
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    super::DelayTestEntity::delete_many()
+        .filter(super::delay_test::Column::Instance.eq(name))
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    super::ServiceRunEntity::delete_many()
+        .filter(super::service_run::Column::Instance.eq(name))
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    Ok(())
+}
+
+// List Instance
+pub(crate) async fn list_instance(
+    conn: &sea_orm::DatabaseConnection,
+) -> Result<Vec<Model>, super::Error> {
+    Entity::find()
+        .all(conn)
+        .await
+        .map_err(super::Error::DBError)
+}
+
+// Set Instance Config
+pub(crate) async fn set_instance_config(
+    conn: &sea_orm::DatabaseConnection,
+    name: String,
+    config_id: String,
+) -> Result<(), super::Error> {
+    modify_instance(
+        conn,
+        name,
+        ActiveModel {
+            config_id: ActiveValue::set(Some(config_id)),
+            ..Default::default()
+        },
+    )
+    .await?;
+    Ok(())
+}
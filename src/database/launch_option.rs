@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+
+use sea_orm::{entity::prelude::*, sea_query::OnConflict, IntoActiveModel};
+use serde::{Deserialize, Serialize};
+
+// Process launch options of the core
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct LaunchOption {
+    pub(crate) env: BTreeMap<String, String>,
+    pub(crate) args: Vec<String>, // appended to `run`
+    pub(crate) working_dir: Option<String>,
+}
+
+impl LaunchOption {
+    fn check(&self) -> Result<(), super::Error> {
+        if self.env.keys().any(|k| k.is_empty() || k.contains('=')) {
+            return Err(super::Error::CustomErr(
+                "invalid environment variable name".to_string(),
+            ));
+        }
+        if matches!(&self.working_dir, Some(dir) if dir.is_empty()) {
+            return Err(super::Error::CustomErr("empty working dir".to_string()));
+        }
+        Ok(())
+    }
+
+    // Config options take precedence over the global ones
+    pub(crate) fn merge(mut self, other: LaunchOption) -> LaunchOption {
+        self.env.extend(other.env);
+        self.args.extend(other.args);
+        if other.working_dir.is_some() {
+            self.working_dir = other.working_dir;
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "launch_option")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub config_id: String,
+    pub option: serde_json::Value, // LaunchOption
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+// Set Config Launch Option
+pub(crate) async fn set_config_launch_option(
+    conn: &sea_orm::DatabaseConnection,
+    config_id: String,
+    option: LaunchOption,
+) -> Result<(), super::Error> {
+    if config_id.is_empty() {
+        return Err(super::Error::ConfigMissingID);
+    }
+    option.check()?;
+    // The config must exist
+    super::get_config(conn, config_id.clone()).await?;
+    let option =
+        serde_json::to_value(option).map_err(|e| super::Error::CustomErr(e.to_string()))?;
+    Entity::insert(Model { config_id, option }.into_active_model())
+        .on_conflict(
+            OnConflict::column(Column::ConfigId)
+                .update_column(Column::Option)
+                .to_owned(),
+        )
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    Ok(())
+}
+
+// Get Config Launch Option
+pub(crate) async fn get_config_launch_option(
+    conn: &sea_orm::DatabaseConnection,
+    config_id: String,
+) -> Result<Option<LaunchOption>, super::Error> {
+    if config_id.is_empty() {
+        return Err(super::Error::ConfigMissingID);
+    }
+    match Entity::find_by_id(&config_id)
+        .one(conn)
+        .await
+        .map_err(super::Error::DBError)?
+    {
+        Some(model) => serde_json::from_value(model.option)
+            .map(Some)
+            .map_err(|e| super::Error::CustomErr(e.to_string())),
+        None => Ok(None),
+    }
+}
+
+// Delete Config Launch Option
+pub(crate) async fn delete_config_launch_option(
+    conn: &sea_orm::DatabaseConnection,
+    config_id: String,
+) -> Result<(), super::Error> {
+    if config_id.is_empty() {
+        return Err(super::Error::ConfigMissingID);
+    }
+    Entity::delete_by_id(&config_id)
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    Ok(())
+}
+
+const KEY_LAUNCH_OPTION: &str = "launch_option";
+
+// Set Global Launch Option
+pub(crate) async fn set_launch_option(
+    conn: &sea_orm::DatabaseConnection,
+    option: LaunchOption,
+) -> Result<(), super::Error> {
+    option.check()?;
+    let value = serde_json::to_value(option).map_err(|e| super::Error::CustomErr(e.to_string()))?;
+    super::set_kv(
+        conn,
+        super::Kv {
+            key: KEY_LAUNCH_OPTION.to_string(),
+            value,
+        },
+    )
+    .await
+    .map(|_| ())
+}
+
+// Get Global Launch Option
+pub(crate) async fn get_launch_option(
+    conn: &sea_orm::DatabaseConnection,
+) -> Result<LaunchOption, super::Error> {
+    match super::get_kv(conn, KEY_LAUNCH_OPTION).await {
+        Ok(kv) => {
+            serde_json::from_value(kv.value).map_err(|e| super::Error::CustomErr(e.to_string()))
+        }
+        Err(super::Error::KvNotFound(_)) => Ok(LaunchOption::default()),
+        Err(e) => Err(e),
+    }
+}
@@ -1,13 +1,35 @@
+mod clash_mode;
 mod common;
 mod config;
 mod database;
+mod delay_test;
 mod error;
+mod instance;
 mod kv;
+mod launch_option;
+mod netns;
+mod process_limit;
+mod proxy_selection;
 mod script;
+mod service_run;
+mod stat;
+mod traffic_total;
 
+pub(crate) use clash_mode::{Entity as ClashModeEntity, Model as ClashMode, *};
 pub(crate) use common::*;
 pub(crate) use config::{ActiveModel as ActiveConfig, Entity as ConfigEntity, Model as Config, *};
 pub(crate) use database::*;
+pub(crate) use delay_test::{Entity as DelayTestEntity, Model as DelayTest, *};
 pub(crate) use error::*;
+pub(crate) use instance::{
+    ActiveModel as ActiveInstance, Entity as InstanceEntity, Model as Instance, *,
+};
 pub(crate) use kv::{Entity as KvEntity, Model as Kv, *};
-pub(crate) use script::{ActiveModel as ActiveScript, Model as Script, Entity as ScriptEntity, *};
+pub(crate) use launch_option::{Entity as LaunchOptionEntity, *};
+pub(crate) use netns::{Entity as NetnsEntity, *};
+pub(crate) use process_limit::{Entity as ProcessLimitEntity, *};
+pub(crate) use proxy_selection::{Entity as ProxySelectionEntity, Model as ProxySelection, *};
+pub(crate) use script::{ActiveModel as ActiveScript, Entity as ScriptEntity, Model as Script, *};
+pub(crate) use service_run::{Entity as ServiceRunEntity, Model as ServiceRun, *};
+pub(crate) use stat::{Entity as StatEntity, Model as Stat, *};
+pub(crate) use traffic_total::{Entity as TrafficTotalEntity, Model as TrafficTotal, *};
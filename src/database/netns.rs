@@ -0,0 +1,239 @@
+use std::net::IpAddr;
+
+use sea_orm::{entity::prelude::*, sea_query::OnConflict, IntoActiveModel};
+use serde::{Deserialize, Serialize};
+
+// Named network namespace (/run/netns/<name>) the core runs in, linux only
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct NetnsOption {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) create: bool, // create the namespace if missing, otherwise join only
+    pub(crate) veth: Option<VethOption>, // required, optional for the saved options only
+}
+
+// Veth pair between the host and the namespace, the clash api is reached through it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct VethOption {
+    pub(crate) host_name: String,
+    pub(crate) peer_name: String,    // interface name in the namespace
+    pub(crate) host_address: String, // CIDR, e.g. 10.200.0.1/30
+    pub(crate) peer_address: String, // CIDR, e.g. 10.200.0.2/30
+    #[serde(default)]
+    pub(crate) default_route: bool, // route via the host address in the namespace
+}
+
+impl VethOption {
+    pub(crate) fn host_ip(&self) -> Option<IpAddr> {
+        parse_cidr(&self.host_address).map(|(ip, _)| ip)
+    }
+
+    pub(crate) fn peer_ip(&self) -> Option<IpAddr> {
+        parse_cidr(&self.peer_address).map(|(ip, _)| ip)
+    }
+}
+
+impl NetnsOption {
+    fn check(&self) -> Result<(), super::Error> {
+        if self.name.is_empty()
+            || self.name.starts_with('.')
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        {
+            return Err(super::Error::CustomErr(format!(
+                "invalid netns name: {}",
+                self.name
+            )));
+        }
+        // The clash api and the readiness probe reach the core through the veth peer
+        let veth = match &self.veth {
+            Some(v) => v,
+            None => {
+                return Err(super::Error::CustomErr(
+                    "veth is required to reach the clash api in the netns".to_string(),
+                ))
+            }
+        };
+        for name in [&veth.host_name, &veth.peer_name] {
+            // IFNAMSIZ
+            if name.is_empty()
+                || name.len() > 15
+                || name.chars().any(|c| c == '/' || c.is_whitespace())
+            {
+                return Err(super::Error::CustomErr(format!(
+                    "invalid interface name: {}",
+                    name
+                )));
+            }
+        }
+        for address in [&veth.host_address, &veth.peer_address] {
+            if parse_cidr(address).is_none() {
+                return Err(super::Error::CustomErr(format!(
+                    "invalid address: {}",
+                    address
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_cidr(s: &str) -> Option<(IpAddr, u8)> {
+    let (ip, prefix) = s.split_once('/')?;
+    let ip: IpAddr = ip.parse().ok()?;
+    let prefix: u8 = prefix.parse().ok()?;
+    let max = if ip.is_ipv4() { 32 } else { 128 };
+    (prefix <= max).then_some((ip, prefix))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "netns")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub instance: String,
+    pub option: serde_json::Value, // NetnsOption
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+// Set Netns
+pub(crate) async fn set_netns(
+    conn: &sea_orm::DatabaseConnection,
+    instance: String,
+    option: NetnsOption,
+) -> Result<(), super::Error> {
+    if instance.is_empty() {
+        return Err(super::Error::InstanceMissingName);
+    }
+    option.check()?;
+    let option =
+        serde_json::to_value(option).map_err(|e| super::Error::CustomErr(e.to_string()))?;
+    Entity::insert(Model { instance, option }.into_active_model())
+        .on_conflict(
+            OnConflict::column(Column::Instance)
+                .update_column(Column::Option)
+                .to_owned(),
+        )
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    Ok(())
+}
+
+// Get Netns
+pub(crate) async fn get_netns(
+    conn: &sea_orm::DatabaseConnection,
+    instance: String,
+) -> Result<Option<NetnsOption>, super::Error> {
+    if instance.is_empty() {
+        return Err(super::Error::InstanceMissingName);
+    }
+    match Entity::find_by_id(&instance)
+        .one(conn)
+        .await
+        .map_err(super::Error::DBError)?
+    {
+        Some(model) => serde_json::from_value(model.option)
+            .map(Some)
+            .map_err(|e| super::Error::CustomErr(e.to_string())),
+        None => Ok(None),
+    }
+}
+
+// Delete Netns
+pub(crate) async fn delete_netns(
+    conn: &sea_orm::DatabaseConnection,
+    instance: String,
+) -> Result<(), super::Error> {
+    if instance.is_empty() {
+        return Err(super::Error::InstanceMissingName);
+    }
+    Entity::delete_by_id(&instance)
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn option(host_address: &str, peer_address: &str) -> NetnsOption {
+        NetnsOption {
+            name: "box".to_string(),
+            create: true,
+            veth: Some(VethOption {
+                host_name: "veth-box".to_string(),
+                peer_name: "eth0".to_string(),
+                host_address: host_address.to_string(),
+                peer_address: peer_address.to_string(),
+                default_route: false,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_parse_cidr() {
+        assert_eq!(
+            parse_cidr("10.200.0.1/30"),
+            Some(("10.200.0.1".parse().unwrap(), 30))
+        );
+        assert_eq!(
+            parse_cidr("fd00::1/126"),
+            Some(("fd00::1".parse().unwrap(), 126))
+        );
+        assert_eq!(parse_cidr("10.200.0.1/32").map(|(_, p)| p), Some(32));
+        assert_eq!(parse_cidr("fd00::1/128").map(|(_, p)| p), Some(128));
+        // The prefix is bounded by the address family
+        assert_eq!(parse_cidr("10.200.0.1/33"), None);
+        assert_eq!(parse_cidr("fd00::1/129"), None);
+        assert_eq!(parse_cidr("10.200.0.1"), None);
+        assert_eq!(parse_cidr("10.200.0.1/"), None);
+        assert_eq!(parse_cidr("10.200.0.256/30"), None);
+        assert_eq!(parse_cidr("host/30"), None);
+        assert_eq!(parse_cidr("10.200.0.1/-1"), None);
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(option("10.200.0.1/30", "10.200.0.2/30").check().is_ok());
+        assert!(option("fd00::1/126", "fd00::2/126").check().is_ok());
+        assert!(option("10.200.0.1/30", "10.200.0.2/40").check().is_err());
+        assert!(option("fd00::1/130", "fd00::2/126").check().is_err());
+
+        let veth = option("10.200.0.1/30", "10.200.0.2/30").veth.unwrap();
+        assert_eq!(veth.host_ip(), Some("10.200.0.1".parse().unwrap()));
+        assert_eq!(veth.peer_ip(), Some("10.200.0.2".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_check_name() {
+        for name in ["", ".box", "../box", "b ox"] {
+            let option = NetnsOption {
+                name: name.to_string(),
+                ..option("10.200.0.1/30", "10.200.0.2/30")
+            };
+            assert!(option.check().is_err(), "{}", name);
+        }
+        for name in ["", "veth-with-a-long-name", "veth/0", "veth 0"] {
+            let mut option = option("10.200.0.1/30", "10.200.0.2/30");
+            option.veth.as_mut().unwrap().host_name = name.to_string();
+            assert!(option.check().is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_check_veth_required() {
+        let option = NetnsOption {
+            veth: None,
+            ..option("10.200.0.1/30", "10.200.0.2/30")
+        };
+        assert!(option.check().is_err());
+    }
+}
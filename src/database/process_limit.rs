@@ -0,0 +1,252 @@
+use sea_orm::{entity::prelude::*, sea_query::OnConflict, IntoActiveModel};
+use serde::{Deserialize, Serialize};
+
+// Linux capability numbers, see capability.h
+const CAPABILITIES: &[(&str, u32)] = &[
+    ("CAP_CHOWN", 0),
+    ("CAP_DAC_OVERRIDE", 1),
+    ("CAP_DAC_READ_SEARCH", 2),
+    ("CAP_FOWNER", 3),
+    ("CAP_KILL", 5),
+    ("CAP_SETGID", 6),
+    ("CAP_SETUID", 7),
+    ("CAP_NET_BIND_SERVICE", 10),
+    ("CAP_NET_BROADCAST", 11),
+    ("CAP_NET_ADMIN", 12),
+    ("CAP_NET_RAW", 13),
+    ("CAP_IPC_LOCK", 14),
+    ("CAP_SYS_CHROOT", 18),
+    ("CAP_SYS_PTRACE", 19),
+    ("CAP_SYS_ADMIN", 21),
+    ("CAP_SYS_NICE", 23),
+    ("CAP_SYS_RESOURCE", 24),
+    ("CAP_SYS_TIME", 25),
+];
+
+// Privileges and resource limits of the core process, linux only
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct ProcessLimit {
+    pub(crate) uid: Option<u32>,
+    pub(crate) gid: Option<u32>,
+    pub(crate) capabilities: Vec<String>, // ambient, kept after switching to uid
+    pub(crate) nofile: Option<u64>,       // RLIMIT_NOFILE
+    pub(crate) memory: Option<u64>,       // B, RLIMIT_DATA
+    pub(crate) nice: Option<i32>,
+    pub(crate) cgroup: Option<String>, // cgroup v2 path under /sys/fs/cgroup
+}
+
+impl ProcessLimit {
+    fn check(&self) -> Result<(), super::Error> {
+        if self.uid.is_some() && self.gid.is_none() {
+            return Err(super::Error::CustomErr(
+                "gid is required with uid".to_string(),
+            ));
+        }
+        if !self.capabilities.is_empty() && self.uid.is_none() {
+            return Err(super::Error::CustomErr(
+                "capabilities require uid".to_string(),
+            ));
+        }
+        self.capability_mask()?;
+        if matches!(self.nice, Some(n) if !(-20..=19).contains(&n)) {
+            return Err(super::Error::CustomErr(
+                "nice must be between -20 and 19".to_string(),
+            ));
+        }
+        if matches!(self.nofile, Some(0)) {
+            return Err(super::Error::CustomErr(
+                "nofile must be greater than 0".to_string(),
+            ));
+        }
+        if let Some(cgroup) = &self.cgroup {
+            let cgroup = cgroup.trim_matches('/');
+            if cgroup.is_empty() || cgroup.split('/').any(|s| s.is_empty() || s == "..") {
+                return Err(super::Error::CustomErr(format!(
+                    "invalid cgroup: {}",
+                    cgroup
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Capability names are case insensitive, the CAP_ prefix is optional
+    pub(crate) fn capability_mask(&self) -> Result<u64, super::Error> {
+        let mut mask = 0u64;
+        for name in &self.capabilities {
+            let mut upper = name.to_ascii_uppercase();
+            if !upper.starts_with("CAP_") {
+                upper.insert_str(0, "CAP_");
+            }
+            match CAPABILITIES.iter().find(|(n, _)| *n == upper) {
+                Some((_, cap)) => mask |= 1 << cap,
+                None => {
+                    return Err(super::Error::CustomErr(format!(
+                        "unknown capability: {}",
+                        name
+                    )))
+                }
+            }
+        }
+        Ok(mask)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "process_limit")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub instance: String,
+    pub limit: serde_json::Value, // ProcessLimit
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+// Set Process Limit
+pub(crate) async fn set_process_limit(
+    conn: &sea_orm::DatabaseConnection,
+    instance: String,
+    limit: ProcessLimit,
+) -> Result<(), super::Error> {
+    if instance.is_empty() {
+        return Err(super::Error::InstanceMissingName);
+    }
+    limit.check()?;
+    let limit = serde_json::to_value(limit).map_err(|e| super::Error::CustomErr(e.to_string()))?;
+    Entity::insert(Model { instance, limit }.into_active_model())
+        .on_conflict(
+            OnConflict::column(Column::Instance)
+                .update_column(Column::Limit)
+                .to_owned(),
+        )
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    Ok(())
+}
+
+// Get Process Limit
+pub(crate) async fn get_process_limit(
+    conn: &sea_orm::DatabaseConnection,
+    instance: String,
+) -> Result<ProcessLimit, super::Error> {
+    if instance.is_empty() {
+        return Err(super::Error::InstanceMissingName);
+    }
+    match Entity::find_by_id(&instance)
+        .one(conn)
+        .await
+        .map_err(super::Error::DBError)?
+    {
+        Some(model) => {
+            serde_json::from_value(model.limit).map_err(|e| super::Error::CustomErr(e.to_string()))
+        }
+        None => Ok(ProcessLimit::default()),
+    }
+}
+
+// Delete Process Limit
+pub(crate) async fn delete_process_limit(
+    conn: &sea_orm::DatabaseConnection,
+    instance: String,
+) -> Result<(), super::Error> {
+    if instance.is_empty() {
+        return Err(super::Error::InstanceMissingName);
+    }
+    Entity::delete_by_id(&instance)
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities(names: &[&str]) -> ProcessLimit {
+        ProcessLimit {
+            uid: Some(1000),
+            gid: Some(1000),
+            capabilities: names.iter().map(|n| n.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_capability_mask() {
+        assert_eq!(capabilities(&[]).capability_mask().unwrap(), 0);
+        assert_eq!(
+            capabilities(&["CAP_NET_ADMIN", "CAP_NET_BIND_SERVICE"])
+                .capability_mask()
+                .unwrap(),
+            (1 << 12) | (1 << 10)
+        );
+        // Case insensitive, the prefix is optional
+        assert_eq!(
+            capabilities(&["net_raw", "Cap_Sys_Time"])
+                .capability_mask()
+                .unwrap(),
+            (1 << 13) | (1 << 25)
+        );
+        assert_eq!(
+            capabilities(&["net_admin", "CAP_NET_ADMIN"])
+                .capability_mask()
+                .unwrap(),
+            1 << 12
+        );
+        for name in ["CAP_UNKNOWN", "", "CAP_", "net admin"] {
+            assert!(capabilities(&[name]).capability_mask().is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(ProcessLimit::default().check().is_ok());
+        assert!(capabilities(&["net_admin"]).check().is_ok());
+        assert!(capabilities(&["unknown"]).check().is_err());
+        // Capabilities are kept across the switch to uid only
+        let limit = ProcessLimit {
+            capabilities: vec!["net_admin".to_string()],
+            ..Default::default()
+        };
+        assert!(limit.check().is_err());
+        let limit = ProcessLimit {
+            uid: Some(1000),
+            ..Default::default()
+        };
+        assert!(limit.check().is_err());
+    }
+
+    #[test]
+    fn test_check_bounds() {
+        let limit = |nice: Option<i32>, nofile: Option<u64>| ProcessLimit {
+            nice,
+            nofile,
+            ..Default::default()
+        };
+        assert!(limit(Some(-20), Some(1)).check().is_ok());
+        assert!(limit(Some(19), Some(1048576)).check().is_ok());
+        assert!(limit(Some(-21), None).check().is_err());
+        assert!(limit(Some(20), None).check().is_err());
+        assert!(limit(None, Some(0)).check().is_err());
+
+        let cgroup = |cgroup: &str| ProcessLimit {
+            cgroup: Some(cgroup.to_string()),
+            ..Default::default()
+        };
+        assert!(cgroup("boxmgr/core").check().is_ok());
+        assert!(cgroup("/boxmgr/").check().is_ok());
+        for invalid in ["", "/", "boxmgr//core", "../core", "boxmgr/../../core"] {
+            assert!(cgroup(invalid).check().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use sea_orm::{entity::prelude::*, sea_query::OnConflict, IntoActiveModel};
+use serde::{Deserialize, Serialize};
+
+// Selected member of a selector outbound, re-applied when the core of the config is ready
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "proxy_selection")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub config_id: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub group: String, // selector tag
+    pub selected: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+// Set Proxy Selection
+pub(crate) async fn set_proxy_selection(
+    conn: &sea_orm::DatabaseConnection,
+    selection: Model,
+) -> Result<(), super::Error> {
+    if selection.config_id.is_empty() {
+        return Err(super::Error::ConfigMissingID);
+    }
+    if selection.group.is_empty() || selection.selected.is_empty() {
+        return Err(super::Error::CustomErr(
+            "empty group or selected".to_string(),
+        ));
+    }
+    Entity::insert(selection.into_active_model())
+        .on_conflict(
+            OnConflict::columns([Column::ConfigId, Column::Group])
+                .update_column(Column::Selected)
+                .to_owned(),
+        )
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    Ok(())
+}
+
+// List Proxy Selection
+pub(crate) async fn list_proxy_selection(
+    conn: &sea_orm::DatabaseConnection,
+    config_id: String,
+) -> Result<Vec<Model>, super::Error> {
+    if config_id.is_empty() {
+        return Err(super::Error::ConfigMissingID);
+    }
+    Entity::find()
+        .filter(Column::ConfigId.eq(config_id))
+        .all(conn)
+        .await
+        .map_err(super::Error::DBError)
+}
+
+// Delete Proxy Selection, all groups of the config if group is None
+pub(crate) async fn delete_proxy_selection(
+    conn: &sea_orm::DatabaseConnection,
+    config_id: String,
+    group: Option<String>,
+) -> Result<(), super::Error> {
+    if config_id.is_empty() {
+        return Err(super::Error::ConfigMissingID);
+    }
+    let mut delete = Entity::delete_many().filter(Column::ConfigId.eq(config_id));
+    if let Some(group) = group {
+        delete = delete.filter(Column::Group.eq(group));
+    }
+    delete.exec(conn).await.map_err(super::Error::DBError)?;
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use sea_orm::{
+    entity::prelude::*, ActiveModelTrait, ActiveValue, IntoActiveModel, QueryOrder, QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::common;
+
+// Who or what started / stopped a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RunReason {
+    Api,
+    AutoStart,
+    AutoRestart,
+    Rollback,
+    Revert,
+    Shutdown,
+    Crash,
+    Exit,    // the core exited by itself with success
+    Unknown, // the manager went away while the core was running
+}
+
+impl RunReason {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Api => "api",
+            Self::AutoStart => "auto_start",
+            Self::AutoRestart => "auto_restart",
+            Self::Rollback => "rollback",
+            Self::Revert => "revert",
+            Self::Shutdown => "shutdown",
+            Self::Crash => "crash",
+            Self::Exit => "exit",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "service_run")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: String,
+    pub instance: String,
+    pub config_id: String,
+    pub config_tag: String,
+    pub core_path: String,
+    pub core_version: String,
+    pub pid: Option<u32>,
+    pub start_reason: String,
+    pub stop_reason: Option<String>,
+    pub start_time: i64,         // unix timestamp (ms)
+    pub ready_time: Option<i64>, // unix timestamp (ms)
+    pub stop_time: Option<i64>,  // unix timestamp (ms)
+    pub exit_code: Option<i32>,
+    pub exit_signal: Option<i32>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+// Add Service Run
+pub(crate) async fn add_service_run(
+    conn: &sea_orm::DatabaseConnection,
+    mut run: Model,
+) -> Result<Model, super::Error> {
+    if run.id.is_empty() {
+        run.id = common::random_uuid().replace('-', "");
+    }
+    run.into_active_model()
+        .insert(conn)
+        .await
+        .map_err(super::Error::DBError)
+}
+
+// Set Service Run Ready
+pub(crate) async fn set_service_run_ready(
+    conn: &sea_orm::DatabaseConnection,
+    id: String,
+    ready_time: i64,
+) -> Result<(), super::Error> {
+    ActiveModel {
+        id: ActiveValue::set(id),
+        ready_time: ActiveValue::set(Some(ready_time)),
+        ..Default::default()
+    }
+    .update(conn)
+    .await
+    .map_err(super::Error::DBError)?;
+    Ok(())
+}
+
+// Finish Service Run
+pub(crate) async fn finish_service_run(
+    conn: &sea_orm::DatabaseConnection,
+    id: String,
+    stop_time: i64,
+    stop_reason: RunReason,
+    exit_code: Option<i32>,
+    exit_signal: Option<i32>,
+) -> Result<(), super::Error> {
+    ActiveModel {
+        id: ActiveValue::set(id),
+        stop_time: ActiveValue::set(Some(stop_time)),
+        stop_reason: ActiveValue::set(Some(stop_reason.as_str().to_string())),
+        exit_code: ActiveValue::set(exit_code),
+        exit_signal: ActiveValue::set(exit_signal),
+        ..Default::default()
+    }
+    .update(conn)
+    .await
+    .map_err(super::Error::DBError)?;
+    Ok(())
+}
+
+// Finish the runs left open by a previous manager process
+pub(crate) async fn finish_orphan_service_run(
+    conn: &sea_orm::DatabaseConnection,
+    stop_time: i64,
+) -> Result<u64, super::Error> {
+    Entity::update_many()
+        .col_expr(Column::StopTime, Expr::value(stop_time))
+        .col_expr(Column::StopReason, Expr::value(RunReason::Unknown.as_str()))
+        .filter(Column::StopTime.is_null())
+        .exec(conn)
+        .await
+        .map(|res| res.rows_affected)
+        .map_err(super::Error::DBError)
+}
+
+// List Service Run: newest first, return the page and the total count
+pub(crate) async fn list_service_run(
+    conn: &sea_orm::DatabaseConnection,
+    instance: String,
+    page: u64,
+    page_size: u64,
+) -> Result<(Vec<Model>, u64), super::Error> {
+    let select = Entity::find().filter(Column::Instance.eq(instance));
+    let total = select
+        .clone()
+        .count(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    let runs = select
+        .order_by_desc(Column::StartTime)
+        .offset(page.saturating_sub(1) * page_size)
+        .limit(page_size)
+        .all(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    Ok((runs, total))
+}
@@ -0,0 +1,161 @@
+use sea_orm::{
+    entity::prelude::*,
+    sea_query::{Expr, OnConflict},
+    IntoActiveModel, QueryOrder, QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+
+// Bucket size of a stat, buckets start at UTC boundaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum StatGranularity {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl StatGranularity {
+    pub(crate) const ALL: [Self; 3] = [Self::Minute, Self::Hour, Self::Day];
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Minute => "minute",
+            Self::Hour => "hour",
+            Self::Day => "day",
+        }
+    }
+
+    // Bucket length (ms)
+    pub(crate) fn duration(&self) -> i64 {
+        match self {
+            Self::Minute => 60 * 1000,
+            Self::Hour => 60 * 60 * 1000,
+            Self::Day => 24 * 60 * 60 * 1000,
+        }
+    }
+
+    // Start of the bucket containing time (ms)
+    pub(crate) fn bucket(&self, time: i64) -> i64 {
+        time - time.rem_euclid(self.duration())
+    }
+}
+
+// Traffic and resource usage of an instance in one bucket
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "stat")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    #[serde(skip)]
+    pub instance: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    #[serde(skip)]
+    pub granularity: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub time: i64, // bucket start, unix timestamp (ms)
+    pub upload: i64,             // B, transferred in the bucket
+    pub download: i64,           // B, transferred in the bucket
+    pub upload_speed: i64,       // B/s, average
+    pub download_speed: i64,     // B/s, average
+    pub upload_speed_max: i64,   // B/s
+    pub download_speed_max: i64, // B/s
+    pub memory: i64,             // B, average
+    pub memory_max: i64,         // B
+    pub connections: i64,        // average
+    pub connections_max: i64,
+    pub samples: i64, // weight of the averages
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+// Add a minute Stat and merge it into the hour and day buckets,
+// a bucket written twice (core restarted within it) is merged too
+pub(crate) async fn add_stat(
+    conn: &sea_orm::DatabaseConnection,
+    stat: Model,
+) -> Result<(), super::Error> {
+    if stat.samples <= 0 {
+        return Ok(());
+    }
+    let sum = |c: &str| Expr::cust(format!("{c} + excluded.{c}"));
+    let max = |c: &str| Expr::cust(format!("MAX({c}, excluded.{c})"));
+    let avg = |c: &str| {
+        Expr::cust(format!(
+            "({c} * samples + excluded.{c} * excluded.samples) / (samples + excluded.samples)"
+        ))
+    };
+    for granularity in StatGranularity::ALL {
+        let stat = Model {
+            granularity: granularity.as_str().to_string(),
+            time: granularity.bucket(stat.time),
+            ..stat.clone()
+        };
+        Entity::insert(stat.into_active_model())
+            .on_conflict(
+                OnConflict::columns([Column::Instance, Column::Granularity, Column::Time])
+                    .values([
+                        (Column::Upload, sum("upload")),
+                        (Column::Download, sum("download")),
+                        (Column::UploadSpeed, avg("upload_speed")),
+                        (Column::DownloadSpeed, avg("download_speed")),
+                        (Column::UploadSpeedMax, max("upload_speed_max")),
+                        (Column::DownloadSpeedMax, max("download_speed_max")),
+                        (Column::Memory, avg("memory")),
+                        (Column::MemoryMax, max("memory_max")),
+                        (Column::Connections, avg("connections")),
+                        (Column::ConnectionsMax, max("connections_max")),
+                        (Column::Samples, sum("samples")),
+                    ])
+                    .to_owned(),
+            )
+            .exec(conn)
+            .await
+            .map_err(super::Error::DBError)?;
+    }
+    Ok(())
+}
+
+// List Stats in time order, the latest `limit` buckets
+pub(crate) async fn list_stat(
+    conn: &sea_orm::DatabaseConnection,
+    instance: String,
+    granularity: StatGranularity,
+    since: Option<i64>,
+    until: Option<i64>,
+    limit: u64,
+) -> Result<Vec<Model>, super::Error> {
+    let mut select = Entity::find()
+        .filter(Column::Instance.eq(instance))
+        .filter(Column::Granularity.eq(granularity.as_str()));
+    if let Some(since) = since {
+        select = select.filter(Column::Time.gte(granularity.bucket(since)));
+    }
+    if let Some(until) = until {
+        select = select.filter(Column::Time.lt(until));
+    }
+    let mut stats = select
+        .order_by_desc(Column::Time)
+        .limit(limit)
+        .all(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    stats.reverse();
+    Ok(stats)
+}
+
+// Delete the buckets of a granularity which started before `before` (ms), of all instances
+pub(crate) async fn prune_stat(
+    conn: &sea_orm::DatabaseConnection,
+    granularity: StatGranularity,
+    before: i64,
+) -> Result<u64, super::Error> {
+    Entity::delete_many()
+        .filter(Column::Granularity.eq(granularity.as_str()))
+        .filter(Column::Time.lt(before))
+        .exec(conn)
+        .await
+        .map(|res| res.rows_affected)
+        .map_err(super::Error::DBError)
+}
@@ -0,0 +1,125 @@
+use sea_orm::{
+    entity::prelude::*,
+    sea_query::{Expr, OnConflict},
+    IntoActiveModel, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+
+// Id of the totals of all configs and instances
+pub(crate) const GLOBAL_TRAFFIC_TOTAL: &str = "global";
+
+// Lifetime traffic of a config (id: config id) or of everything (id: global)
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "traffic_total")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: String,
+    pub upload: i64,   // B
+    pub download: i64, // B
+    pub since: i64,    // unix timestamp (ms), of the first count or the last reset
+    pub updated: i64,  // unix timestamp (ms)
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+// Add traffic to the totals of the config and the global totals
+pub(crate) async fn add_traffic_total(
+    conn: &sea_orm::DatabaseConnection,
+    config_id: String,
+    upload: i64,
+    download: i64,
+    time: i64,
+) -> Result<(), super::Error> {
+    if config_id.is_empty() {
+        return Err(super::Error::ConfigMissingID);
+    }
+    if upload == 0 && download == 0 {
+        return Ok(());
+    }
+    for id in [config_id, GLOBAL_TRAFFIC_TOTAL.to_string()] {
+        Entity::insert(
+            Model {
+                id,
+                upload,
+                download,
+                since: time,
+                updated: time,
+            }
+            .into_active_model(),
+        )
+        .on_conflict(
+            OnConflict::column(Column::Id)
+                .values([
+                    (Column::Upload, Expr::cust("upload + excluded.upload")),
+                    (Column::Download, Expr::cust("download + excluded.download")),
+                ])
+                .update_column(Column::Updated)
+                .to_owned(),
+        )
+        .exec(conn)
+        .await
+        .map_err(super::Error::DBError)?;
+    }
+    Ok(())
+}
+
+// Get Traffic Total, None if nothing is counted yet
+pub(crate) async fn get_traffic_total(
+    conn: &sea_orm::DatabaseConnection,
+    id: String,
+) -> Result<Option<Model>, super::Error> {
+    Entity::find_by_id(id)
+        .one(conn)
+        .await
+        .map_err(super::Error::DBError)
+}
+
+// List the Traffic Totals of the configs, the most used first
+pub(crate) async fn list_config_traffic_total(
+    conn: &sea_orm::DatabaseConnection,
+) -> Result<Vec<Model>, super::Error> {
+    Entity::find()
+        .filter(Column::Id.ne(GLOBAL_TRAFFIC_TOTAL))
+        .order_by_desc(Expr::cust("upload + download"))
+        .all(conn)
+        .await
+        .map_err(super::Error::DBError)
+}
+
+// Reset Traffic Total to zero, counting again since `time`
+pub(crate) async fn reset_traffic_total(
+    conn: &sea_orm::DatabaseConnection,
+    id: String,
+    time: i64,
+) -> Result<(), super::Error> {
+    if id.is_empty() {
+        return Err(super::Error::ConfigMissingID);
+    }
+    Entity::insert(
+        Model {
+            id,
+            upload: 0,
+            download: 0,
+            since: time,
+            updated: time,
+        }
+        .into_active_model(),
+    )
+    .on_conflict(
+        OnConflict::column(Column::Id)
+            .update_columns([
+                Column::Upload,
+                Column::Download,
+                Column::Since,
+                Column::Updated,
+            ])
+            .to_owned(),
+    )
+    .exec(conn)
+    .await
+    .map_err(super::Error::DBError)?;
+    Ok(())
+}
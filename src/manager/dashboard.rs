@@ -0,0 +1,488 @@
+use std::{
+    fs,
+    io::{self, Read, Seek},
+    path::{Component, Path, PathBuf},
+};
+
+const DASHBOARD_DIR: &str = "dashboard";
+const INDEX_FILE: &str = "index.html";
+
+// Dashboards are unpacked to <data_dir>/dashboard/<name> and served at /dashboard/<name>/
+pub(crate) fn dashboard_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join(DASHBOARD_DIR)
+}
+
+pub(crate) fn check_dashboard_name(name: &str) -> Result<(), String> {
+    if name.is_empty()
+        || name.len() > 64
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!("invalid dashboard name: {}", name));
+    }
+    Ok(())
+}
+
+// Names of the installed dashboards, sorted
+pub(crate) fn list_dashboard(data_dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    let dir = match fs::read_dir(dashboard_dir(data_dir)) {
+        Ok(v) => v,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(names),
+        Err(e) => return Err(e),
+    };
+    for entry in dir {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if let Some(name) = entry.file_name().to_str() {
+            if check_dashboard_name(name).is_ok() {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+pub(crate) fn delete_dashboard(data_dir: &Path, name: &str) -> io::Result<bool> {
+    match fs::remove_dir_all(dashboard_dir(data_dir).join(name)) {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// File of a dashboard, None if the path leaves the dashboard dir
+pub(crate) fn dashboard_file(data_dir: &Path, name: &str, path: &str) -> Option<PathBuf> {
+    let mut file = dashboard_dir(data_dir).join(name);
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(s) => file.push(s),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(file)
+}
+
+// Unpack a zip or tar.gz archive (detected by its magic bytes) as the dashboard `name`,
+// replacing the installed one. A single top level directory in the archive is used as the root.
+pub(crate) fn install_dashboard(data_dir: &Path, name: &str, archive: &Path) -> Result<(), String> {
+    let dir = dashboard_dir(data_dir);
+    fs::create_dir_all(&dir).map_err(|e| format!("failed to create dashboard dir: {}", e))?;
+    let temp_dir = dir.join(format!(".{}.temp", name));
+    let _ = fs::remove_dir_all(&temp_dir);
+    let res = unpack(archive, &temp_dir).and_then(|_| {
+        let root = find_root(&temp_dir)?;
+        let target = dir.join(name);
+        let _ = fs::remove_dir_all(&target);
+        fs::rename(root, &target).map_err(|e| format!("failed to install dashboard: {}", e))
+    });
+    let _ = fs::remove_dir_all(&temp_dir);
+    res
+}
+
+// Only regular files and directories are unpacked, a link could point out of the dashboard
+fn unpack(archive: &Path, dest: &Path) -> Result<(), String> {
+    let mut file = fs::File::open(archive).map_err(|e| format!("failed to open archive: {}", e))?;
+    let mut magic = [0u8; 4];
+    let n = file
+        .read(&mut magic)
+        .map_err(|e| format!("failed to read archive: {}", e))?;
+    file.rewind()
+        .map_err(|e| format!("failed to read archive: {}", e))?;
+    fs::create_dir_all(dest).map_err(|e| format!("failed to create unpack dir: {}", e))?;
+    match &magic[..n] {
+        [0x50, 0x4b, 0x03, 0x04] => unpack_zip(file, dest),
+        [0x1f, 0x8b, ..] => unpack_tar_gz(file, dest),
+        _ => Err("unsupported archive, expect zip or tar.gz".to_string()),
+    }
+}
+
+fn unpack_zip(file: fs::File, dest: &Path) -> Result<(), String> {
+    const S_IFMT: u32 = 0o170000;
+    const S_IFREG: u32 = 0o100000;
+    const S_IFDIR: u32 = 0o040000;
+
+    let f = |e: zip::result::ZipError| format!("failed to unpack zip: {}", e);
+    let mut zip = zip::ZipArchive::new(file).map_err(f)?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(f)?;
+        let file_type = entry.unix_mode().map(|mode| mode & S_IFMT).unwrap_or(0);
+        if entry.is_symlink() || (file_type != 0 && file_type != S_IFREG && file_type != S_IFDIR) {
+            return Err(format!(
+                "failed to unpack zip: unsupported entry: {}",
+                entry.name()
+            ));
+        }
+        let path = match entry.enclosed_name() {
+            Some(v) => dest.join(v),
+            None => {
+                return Err(format!(
+                    "failed to unpack zip: invalid path: {}",
+                    entry.name()
+                ))
+            }
+        };
+        let res = if entry.is_dir() {
+            fs::create_dir_all(&path)
+        } else {
+            path.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::File::create(&path))
+                .and_then(|mut out| io::copy(&mut entry, &mut out))
+                .map(|_| ())
+        };
+        res.map_err(|e| format!("failed to unpack zip: {}", e))?;
+    }
+    Ok(())
+}
+
+fn unpack_tar_gz(file: fs::File, dest: &Path) -> Result<(), String> {
+    let f = |e: io::Error| format!("failed to unpack tar.gz: {}", e);
+    let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    for entry in tar.entries().map_err(f)? {
+        let mut entry = entry.map_err(f)?;
+        let path = entry.path().map_err(f)?.into_owned();
+        match entry.header().entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Directory => {}
+            // Pax metadata, e.g. the comment written by git archive
+            tar::EntryType::XGlobalHeader | tar::EntryType::XHeader => continue,
+            _ => {
+                return Err(format!(
+                    "failed to unpack tar.gz: unsupported entry: {}",
+                    path.display()
+                ))
+            }
+        }
+        if path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(format!(
+                "failed to unpack tar.gz: invalid path: {}",
+                path.display()
+            ));
+        }
+        entry.unpack_in(dest).map_err(f)?;
+    }
+    Ok(())
+}
+
+fn find_root(dir: &Path) -> Result<PathBuf, String> {
+    if dir.join(INDEX_FILE).is_file() {
+        return Ok(dir.to_path_buf());
+    }
+    let entries = fs::read_dir(dir)
+        .and_then(|d| d.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("failed to read unpacked dashboard: {}", e))?;
+    if let [entry] = entries.as_slice() {
+        if entry.path().join(INDEX_FILE).is_file() {
+            return Ok(entry.path());
+        }
+    }
+    Err(format!("{} not found in the archive", INDEX_FILE))
+}
+
+// yacd reads the controller from `data-base-url` of its root element, None if not found
+pub(crate) fn inject_controller(index: &str, controller: &str) -> Option<String> {
+    const ATTR: &str = "data-base-url=\"";
+    let value_start = index.find(ATTR)? + ATTR.len();
+    let value_end = value_start + index[value_start..].find('"')?;
+    Some(format!(
+        "{}{}{}",
+        &index[..value_start],
+        escape_html(controller),
+        &index[value_end..]
+    ))
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Split a Host header into host and port, None if it is not host[:port].
+// The host is a domain, an ipv4 or a bracketed ipv6 address
+pub(crate) fn split_host(host: &str) -> Option<(&str, Option<u16>)> {
+    let (hostname, port) = match host.rsplit_once(':') {
+        Some((h, p)) if !h.starts_with('[') || h.ends_with(']') => (h, Some(p)),
+        _ => (host, None),
+    };
+    let valid = match hostname.strip_prefix('[') {
+        Some(ip) => ip
+            .strip_suffix(']')
+            .and_then(|ip| ip.parse::<std::net::Ipv6Addr>().ok())
+            .is_some(),
+        None => {
+            !hostname.is_empty()
+                && hostname
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+        }
+    };
+    if !valid {
+        return None;
+    }
+    match port {
+        Some(p) => p.parse::<u16>().ok().map(|p| (hostname, Some(p))),
+        None => Some((hostname, None)),
+    }
+}
+
+// Percent-encode a query value, only the unreserved characters are kept
+pub(crate) fn encode_query_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            b => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn tar_gz(
+        path: &Path,
+        build: impl FnOnce(&mut tar::Builder<flate2::write::GzEncoder<fs::File>>),
+    ) {
+        let gz = flate2::write::GzEncoder::new(
+            fs::File::create(path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(gz);
+        build(&mut builder);
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn append_file(builder: &mut tar::Builder<impl Write>, name: &str, data: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, name, data).unwrap();
+    }
+
+    fn zip(path: &Path, build: impl FnOnce(&mut zip::ZipWriter<fs::File>)) {
+        let mut writer = zip::ZipWriter::new(fs::File::create(path).unwrap());
+        build(&mut writer);
+        writer.finish().unwrap();
+    }
+
+    fn zip_file(writer: &mut zip::ZipWriter<fs::File>, name: &str, data: &[u8]) {
+        writer
+            .start_file(name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(data).unwrap();
+    }
+
+    #[test]
+    fn test_dashboard_file() {
+        let data_dir = Path::new("/data");
+        assert_eq!(
+            dashboard_file(data_dir, "yacd", "assets/index.js"),
+            Some(PathBuf::from("/data/dashboard/yacd/assets/index.js"))
+        );
+        assert_eq!(
+            dashboard_file(data_dir, "yacd", "./index.html"),
+            Some(PathBuf::from("/data/dashboard/yacd/index.html"))
+        );
+        assert_eq!(
+            dashboard_file(data_dir, "yacd", "../other/index.html"),
+            None
+        );
+        assert_eq!(dashboard_file(data_dir, "yacd", "assets/../../x"), None);
+        assert_eq!(dashboard_file(data_dir, "yacd", "/etc/hostname"), None);
+    }
+
+    #[test]
+    fn test_unpack_tar_gz_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("a.tar.gz");
+        tar_gz(&archive, |builder| {
+            append_file(builder, "index.html", b"<html></html>");
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, "leak", "/").unwrap();
+        });
+        let dest = dir.path().join("out");
+        assert!(unpack(&archive, &dest).is_err());
+        assert!(fs::symlink_metadata(dest.join("leak")).is_err());
+    }
+
+    #[test]
+    fn test_unpack_tar_gz_parent_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("a.tar.gz");
+        tar_gz(&archive, |builder| {
+            // The builder rejects `..`, write the name by hand
+            let data = b"evil";
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..7].copy_from_slice(b"../evil");
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, &data[..]).unwrap();
+        });
+        let dest = dir.path().join("out");
+        assert!(unpack(&archive, &dest).is_err());
+        assert!(!dir.path().join("evil").exists());
+    }
+
+    #[test]
+    fn test_unpack_zip_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("a.zip");
+        zip(&archive, |writer| {
+            zip_file(writer, "index.html", b"<html></html>");
+            writer
+                .add_symlink("leak", "/", zip::write::SimpleFileOptions::default())
+                .unwrap();
+        });
+        let dest = dir.path().join("out");
+        assert!(unpack(&archive, &dest).is_err());
+        assert!(fs::symlink_metadata(dest.join("leak")).is_err());
+    }
+
+    #[test]
+    fn test_unpack_zip_parent_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("a.zip");
+        zip(&archive, |writer| zip_file(writer, "../evil", b"evil"));
+        let dest = dir.path().join("out");
+        assert!(unpack(&archive, &dest).is_err());
+        assert!(!dir.path().join("evil").exists());
+    }
+
+    #[test]
+    fn test_unpack_unsupported() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("a.txt");
+        fs::write(&archive, "not an archive").unwrap();
+        assert!(unpack(&archive, &dir.path().join("out")).is_err());
+    }
+
+    #[test]
+    fn test_install_dashboard_single_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("a.zip");
+        zip(&archive, |writer| {
+            zip_file(writer, "dist/index.html", b"<html></html>");
+            zip_file(writer, "dist/assets/index.js", b"js");
+        });
+        let data_dir = dir.path().join("data");
+        install_dashboard(&data_dir, "yacd", &archive).unwrap();
+        let root = dashboard_dir(&data_dir).join("yacd");
+        assert!(root.join("index.html").is_file());
+        assert!(root.join("assets/index.js").is_file());
+        assert_eq!(list_dashboard(&data_dir).unwrap(), vec!["yacd".to_string()]);
+    }
+
+    #[test]
+    fn test_install_dashboard_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("a.tar.gz");
+        tar_gz(&archive, |builder| {
+            append_file(builder, "index.html", b"<html></html>");
+            append_file(builder, "assets/index.js", b"js");
+        });
+        let data_dir = dir.path().join("data");
+        install_dashboard(&data_dir, "metacubexd", &archive).unwrap();
+        let root = dashboard_dir(&data_dir).join("metacubexd");
+        assert!(root.join("index.html").is_file());
+        assert!(root.join("assets/index.js").is_file());
+    }
+
+    #[test]
+    fn test_install_dashboard_without_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("a.zip");
+        zip(&archive, |writer| {
+            zip_file(writer, "a/index.html", b"<html></html>");
+            zip_file(writer, "b/index.html", b"<html></html>");
+        });
+        let data_dir = dir.path().join("data");
+        assert!(install_dashboard(&data_dir, "yacd", &archive).is_err());
+        assert!(!dashboard_dir(&data_dir).join("yacd").exists());
+    }
+
+    #[test]
+    fn test_inject_controller() {
+        let index = r#"<div id="app" data-base-url="http://127.0.0.1:9090"></div>"#;
+        assert_eq!(
+            inject_controller(index, "http://host:9090/api/v1/clash").as_deref(),
+            Some(r#"<div id="app" data-base-url="http://host:9090/api/v1/clash"></div>"#)
+        );
+        assert_eq!(
+            inject_controller(r#"<div data-base-url=""></div>"#, "x").as_deref(),
+            Some(r#"<div data-base-url="x"></div>"#)
+        );
+        assert_eq!(inject_controller(r#"<div id="app"></div>"#, "x"), None);
+        assert_eq!(
+            inject_controller(r#"<div data-base-url="x></div>"#, "x"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_inject_controller_escape() {
+        let index = r#"<div id="app" data-base-url=""></div>"#;
+        assert_eq!(
+            inject_controller(index, r#"x"><script>alert('1')</script>&"#).as_deref(),
+            Some(
+                r#"<div id="app" data-base-url="x&quot;&gt;&lt;script&gt;alert(&#39;1&#39;)&lt;/script&gt;&amp;"></div>"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_split_host() {
+        assert_eq!(split_host("127.0.0.1"), Some(("127.0.0.1", None)));
+        assert_eq!(
+            split_host("example.com:9090"),
+            Some(("example.com", Some(9090)))
+        );
+        assert_eq!(split_host("[::1]"), Some(("[::1]", None)));
+        assert_eq!(split_host("[fe80::1]:80"), Some(("[fe80::1]", Some(80))));
+        assert_eq!(split_host(""), None);
+        assert_eq!(split_host(":80"), None);
+        assert_eq!(split_host("host:"), None);
+        assert_eq!(split_host("host:99999"), None);
+        assert_eq!(split_host("host:80:80"), None);
+        assert_eq!(split_host("::1"), None);
+        assert_eq!(split_host("[::1"), None);
+        assert_eq!(split_host("[host]:80"), None);
+        assert_eq!(split_host("user@host"), None);
+        assert_eq!(split_host(r#"x"><script>"#), None);
+    }
+
+    #[test]
+    fn test_encode_query_value() {
+        assert_eq!(encode_query_value("example.com"), "example.com");
+        assert_eq!(encode_query_value("[::1]"), "%5B%3A%3A1%5D");
+        assert_eq!(encode_query_value("a&b=c d"), "a%26b%3Dc%20d");
+    }
+}
@@ -11,7 +11,7 @@ use axum::{
     extract::{DefaultBodyLimit, Request},
     http::StatusCode,
     response::{IntoResponse, Redirect, Response},
-    routing::{delete, get, patch, post, put},
+    routing::{any, delete, get, patch, post, put},
     Router,
 };
 use tokio::net::TcpListener;
@@ -89,10 +89,20 @@ impl HTTPServer {
             .merge(Self::kv_router())
             .merge(Self::script_router())
             .merge(Self::service_router())
-            .merge(Self::manager_router());
+            .merge(Self::instance_router())
+            .merge(Self::manager_router())
+            .merge(Self::dashboard_router());
         api_router = api_router.layer(AsyncRequireAuthorizationLayer::new(AuthMiddleware {
-            secret,
+            secret: secret.clone(),
+            clash_token: false,
         }));
+        // Clash API: preflight requests of external dashboards are answered before auth
+        api_router = api_router.merge(Self::cors(Self::clash_router().layer(
+            AsyncRequireAuthorizationLayer::new(AuthMiddleware {
+                secret: secret.clone(),
+                clash_token: true,
+            }),
+        )));
         // Request Body Limit
         // 256 MB
         api_router = api_router
@@ -101,11 +111,25 @@ impl HTTPServer {
         // Cors
         // api_router = Self::cors(api_router);
         //
-        let api_router = api_router.with_state::<()>(manager);
+        // Metrics: every request is counted, rejected ones too
+        api_router = api_router.layer(axum::middleware::from_fn_with_state(
+            manager.clone(),
+            count_request,
+        ));
+        let api_router = api_router.with_state::<()>(manager.clone());
         //
         let mut router = Router::new();
         router = router
             .nest_service("/", get(webui))
+            .merge(Self::dashboard_static_router().with_state::<()>(manager.clone()))
+            .merge(
+                Self::metrics_router()
+                    .layer(AsyncRequireAuthorizationLayer::new(AuthMiddleware {
+                        secret,
+                        clash_token: false,
+                    }))
+                    .with_state::<()>(manager),
+            )
             .fallback(|| async { Redirect::temporary("/") });
         router = router.nest_service("/api/v1", api_router);
         router
@@ -119,7 +143,10 @@ impl HTTPServer {
             .merge(Self::kv_router())
             .merge(Self::script_router())
             .merge(Self::service_router())
-            .merge(Self::manager_router());
+            .merge(Self::instance_router())
+            .merge(Self::manager_router())
+            .merge(Self::dashboard_router())
+            .merge(Self::cors(Self::clash_router()));
         // Request Body Limit
         // 256 MB
         api_router = api_router
@@ -128,11 +155,18 @@ impl HTTPServer {
         // Cors
         // api_router = Self::cors(api_router);
         //
-        let api_router = api_router.with_state::<()>(manager);
+        // Metrics: every request is counted, rejected ones too
+        api_router = api_router.layer(axum::middleware::from_fn_with_state(
+            manager.clone(),
+            count_request,
+        ));
+        let api_router = api_router.with_state::<()>(manager.clone());
         //
         let mut router = Router::new();
         router = router
             .nest_service("/", get(webui))
+            .merge(Self::dashboard_static_router().with_state::<()>(manager.clone()))
+            .merge(Self::metrics_router().with_state::<()>(manager))
             .fallback(|| async { Redirect::temporary("/") });
         router = router.nest_service("/api/v1", api_router);
         router
@@ -148,6 +182,39 @@ impl HTTPServer {
             .route("/config", get(api::config::list_config))
             .route("/active_config/:id", put(api::config::set_active_config))
             .route("/active_config", get(api::config::get_active_config))
+            .route(
+                "/config/:id/launch_option",
+                get(api::config::get_launch_option),
+            )
+            .route(
+                "/config/:id/launch_option",
+                put(api::config::set_launch_option),
+            )
+            .route(
+                "/config/:id/launch_option",
+                delete(api::config::delete_launch_option),
+            )
+            .route(
+                "/config/:id/proxy_selection",
+                get(api::config::list_proxy_selection),
+            )
+            .route(
+                "/config/:id/proxy_selection",
+                delete(api::config::delete_proxy_selection),
+            )
+            .route("/config/:id/clash_mode", get(api::config::get_clash_mode))
+            .route(
+                "/config/:id/clash_mode",
+                delete(api::config::delete_clash_mode),
+            )
+            .route(
+                "/config/:id/traffic_total",
+                get(api::config::get_traffic_total),
+            )
+            .route(
+                "/config/:id/traffic_total",
+                delete(api::config::reset_traffic_total),
+            )
     }
 
     fn kv_router() -> Router<Arc<super::Manager>> {
@@ -204,19 +271,120 @@ impl HTTPServer {
             )
     }
 
-    fn service_router() -> Router<Arc<super::Manager>> {
+    // Routes which also serve named instances under ../instance/:name
+    fn service_instance_router() -> Router<Arc<super::Manager>> {
         Router::new()
             .route("/service/start", get(api::service::start_service))
             .route("/service/stop", get(api::service::stop_service))
             .route("/service/restart", get(api::service::restart_service))
+            .route("/service/reload", get(api::service::reload_service))
+            .route("/service/apply_config/:id", put(api::service::apply_config))
+            .route("/service/confirm_config", get(api::service::confirm_config))
+            .route("/service/config", get(api::service::get_config))
+            .route("/service/status", get(api::service::get_status))
+            .route("/service/log", get(api::service::get_log))
+            .route("/service/runs", get(api::service::list_run))
+            .route("/service/connections", get(api::service::get_connections))
+            .route(
+                "/service/connections",
+                delete(api::service::close_connections),
+            )
+            .route(
+                "/service/connections/:id",
+                delete(api::service::close_connection),
+            )
+            .route("/service/proxies", get(api::service::get_proxy_groups))
+            .route("/service/proxies/:group", put(api::service::select_proxy))
+            .route("/service/mode", get(api::service::get_clash_mode))
+            .route("/service/mode", put(api::service::set_clash_mode))
+            .route("/service/dns/query", get(api::service::dns_query))
+            .route("/service/cache/flush", post(api::service::flush_cache))
+            .route("/service/stats", get(api::service::list_stat))
+            .route("/service/delay_tests", post(api::service::test_delay))
+            .route("/service/delay_tests", get(api::service::list_delay_test))
+            .route(
+                "/service/delay_tests/summary",
+                get(api::service::get_delay_summary),
+            )
+            .route(
+                "/service/connections/stream",
+                get(api::service::stream_connections),
+            )
+            .route(
+                "/service/process_limit",
+                get(api::service::get_process_limit),
+            )
+            .route(
+                "/service/process_limit",
+                put(api::service::set_process_limit),
+            )
+            .route(
+                "/service/process_limit",
+                delete(api::service::delete_process_limit),
+            )
+            .route("/service/netns", get(api::service::get_netns))
+            .route("/service/netns", put(api::service::set_netns))
+            .route("/service/netns", delete(api::service::delete_netns))
+    }
+
+    fn service_router() -> Router<Arc<super::Manager>> {
+        Router::new()
+            .merge(Self::service_instance_router())
             .route("/service/core_path", put(api::service::set_core_path))
             .route("/service/core_path", get(api::service::get_core_path))
             .route("/service/core_path", post(api::service::upload_core_path))
-            .route("/service/config", get(api::service::get_config))
             .route("/service/auto_start", get(api::service::get_auto_start))
             .route("/service/auto_start", put(api::service::set_auto_start))
-            .route("/service/status", get(api::service::get_status))
-            .route("/service/log", get(api::service::get_log))
+            .route(
+                "/service/restart_policy",
+                get(api::service::get_restart_policy),
+            )
+            .route(
+                "/service/restart_policy",
+                put(api::service::set_restart_policy),
+            )
+            .route(
+                "/service/ready_timeout",
+                get(api::service::get_ready_timeout),
+            )
+            .route(
+                "/service/ready_timeout",
+                put(api::service::set_ready_timeout),
+            )
+            .route(
+                "/service/launch_option",
+                get(api::service::get_launch_option),
+            )
+            .route(
+                "/service/launch_option",
+                put(api::service::set_launch_option),
+            )
+            .route(
+                "/service/stat_retention",
+                get(api::service::get_stat_retention),
+            )
+            .route(
+                "/service/stat_retention",
+                put(api::service::set_stat_retention),
+            )
+            .route(
+                "/service/traffic_total",
+                get(api::service::get_traffic_total),
+            )
+            .route(
+                "/service/traffic_total",
+                delete(api::service::reset_traffic_total),
+            )
+    }
+
+    fn instance_router() -> Router<Arc<super::Manager>> {
+        Router::new()
+            .route("/instance", post(api::instance::add_instance))
+            .route("/instance/:name", get(api::instance::get_instance))
+            .route("/instance/:name", patch(api::instance::modify_instance))
+            .route("/instance/:name", delete(api::instance::delete_instance))
+            .route("/instance", get(api::instance::list_instance))
+            .nest("/instance/:name", Self::service_instance_router())
     }
 
     fn manager_router() -> Router<Arc<super::Manager>> {
@@ -226,7 +394,43 @@ impl HTTPServer {
         )
     }
 
-    #[allow(dead_code)]
+    fn dashboard_router() -> Router<Arc<super::Manager>> {
+        Router::new()
+            .route("/dashboard", get(api::dashboard::list_dashboard))
+            .route("/dashboard/:name", post(api::dashboard::upload_dashboard))
+            .route("/dashboard/:name", delete(api::dashboard::delete_dashboard))
+    }
+
+    // Uploaded dashboards, served without auth next to the embedded ui
+    fn dashboard_static_router() -> Router<Arc<super::Manager>> {
+        Router::new()
+            .route(
+                "/dashboard/:name",
+                get(|name: axum::extract::Path<String>| async move {
+                    Redirect::permanent(&format!("/dashboard/{}/", name.0))
+                }),
+            )
+            .route("/dashboard/:name/", get(api::dashboard::serve_dashboard))
+            .route(
+                "/dashboard/:name/*path",
+                get(api::dashboard::serve_dashboard),
+            )
+    }
+
+    // Prometheus scrapes /metrics by default, so it is not under /api/v1
+    fn metrics_router() -> Router<Arc<super::Manager>> {
+        Router::new().route("/metrics", get(api::manager::get_metrics))
+    }
+
+    // Clash API of the running core, for dashboards like yacd
+    fn clash_router() -> Router<Arc<super::Manager>> {
+        Router::new()
+            .route("/clash", any(api::clash::proxy))
+            .route("/clash/*path", any(api::clash::proxy))
+            .route("/instance/:name/clash", any(api::clash::proxy))
+            .route("/instance/:name/clash/*path", any(api::clash::proxy))
+    }
+
     fn cors<T: Clone + Send + Sync + 'static>(router: Router<T>) -> Router<T> {
         router.layer(
             CorsLayer::new()
@@ -265,11 +469,27 @@ async fn webui(req: Request) -> impl IntoResponse {
     }
 }
 
+// Metrics
+
+async fn count_request(
+    manager: axum::extract::State<Arc<super::Manager>>,
+    request: Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let method = request.method().clone();
+    let response = next.run(request).await;
+    manager
+        .get_metrics()
+        .add_api_request(&method, response.status());
+    response
+}
+
 // Auth
 
 #[derive(Clone)]
 pub(crate) struct AuthMiddleware {
     secret: String,
+    clash_token: bool, // also accept ?token= of clash dashboards, only for the clash api proxy
 }
 
 impl AsyncAuthorizeRequest<Body> for AuthMiddleware {
@@ -285,6 +505,7 @@ impl AsyncAuthorizeRequest<Body> for AuthMiddleware {
 
     fn authorize(&mut self, request: http::Request<Body>) -> Self::Future {
         let secret = self.secret.clone();
+        let clash_token = self.clash_token;
         Box::pin(async move {
             // Check Secret: GET /check_secret (header: Authorization: Bearer <secret>)
             const CHECK_SECRET_PATH: &str = "/check_secret";
@@ -319,7 +540,7 @@ impl AsyncAuthorizeRequest<Body> for AuthMiddleware {
                         })
                         .collect::<Vec<(String, String)>>();
                     for (k, v) in querys {
-                        if k == "secret" {
+                        if k == "secret" || (clash_token && k == "token") {
                             if v == secret.as_str() {
                                 return Ok(request);
                             }
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     error::Error,
     fs,
     net::SocketAddr,
@@ -66,10 +67,12 @@ pub struct Manager {
     database_url: Option<String>,
     database: sync::RwLock<Option<Database>>,
     service: sync::RwLock<Option<Service>>,
+    instances: sync::RwLock<HashMap<String, Service>>,
     http_server: sync::Mutex<Option<super::HTTPServer>>,
     data_dir: PathBuf,
     temp_dir: PathBuf,
     exit_token: CancellationToken,
+    metrics: super::Metrics,
 }
 
 impl Manager {
@@ -86,13 +89,15 @@ impl Manager {
             database_url: options.database_url,
             database: sync::RwLock::new(None),
             service: sync::RwLock::new(None),
+            instances: sync::RwLock::new(HashMap::new()),
             http_server: sync::Mutex::new(None),
             data_dir: options.data_dir,
             temp_dir: options.temp_dir,
             exit_token: CancellationToken::new(),
+            metrics: super::Metrics::default(),
         });
         // Set Service
-        let service = service::Service::new(s.clone());
+        let service = service::Service::new(s.clone(), database::DEFAULT_INSTANCE.to_string());
         *s.service.write().unwrap() = Some(service);
         //
         // Set HTTP Server
@@ -108,7 +113,7 @@ impl Manager {
     }
 
     pub async fn run(
-        &self,
+        self: &Arc<Self>,
         cancel_token: CancellationToken,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
         log::info!("Manager is running");
@@ -130,6 +135,12 @@ impl Manager {
         let db = database::Database::new(&database_url).await.map_err(|e| {
             Into::<Box<dyn Error + Send + Sync>>::into(format!("Database connect failed: {}", e))
         })?;
+        // The core processes of the last run are gone with their manager
+        if let Err(e) =
+            database::finish_orphan_service_run(&db, chrono::Utc::now().timestamp_millis()).await
+        {
+            log::error!("finish orphan service run failed: {}", e);
+        }
         *self.database.write().unwrap() = Some(db);
         log::info!("Database is connected");
         //
@@ -140,6 +151,22 @@ impl Manager {
             Into::<Box<dyn Error + Send + Sync>>::into(format!("Service Start Error: {}", e))
         })?;
         log::info!("Service is started");
+        // Named Instances
+        let instances = database::list_instance(&self.get_database())
+            .await
+            .map_err(|e| {
+                Into::<Box<dyn Error + Send + Sync>>::into(format!("List Instance Error: {}", e))
+            })?;
+        for instance in instances {
+            let service = self.add_instance(instance.name.clone());
+            log::info!("Instance {} is starting...", instance.name);
+            service.start().await.map_err(|e| {
+                Into::<Box<dyn Error + Send + Sync>>::into(format!(
+                    "Instance {} Start Error: {}",
+                    instance.name, e
+                ))
+            })?;
+        }
         log::info!("HTTP Server is running on {}", &http_server.listen);
         let exit_token = self.exit_token.clone();
         tokio::select! {
@@ -153,16 +180,42 @@ impl Manager {
             }
         }
         log::warn!("HTTP Server is stopped");
+        let instances = self.instances.write().unwrap().drain().collect::<Vec<_>>();
+        for (name, service) in instances {
+            let _ = service.close().await;
+            log::info!("Instance {} is stopped", name);
+        }
+        let _ = service.close().await;
+        log::info!("Service is stopped");
+        // Closed after the services, which record their runs on stop
         if let Some(db) = self.database.read().unwrap().clone().take() {
             log::info!("Close Database Connection");
             let _ = db.close().await;
         }
-        let _ = service.close().await;
-        log::info!("Service is stopped");
         log::info!("Manager is stopped");
         Ok(())
     }
 
+    // Without logger and http server, on an in-memory database
+    #[cfg(test)]
+    pub(crate) async fn new_test(data_dir: PathBuf) -> Arc<Self> {
+        let db = database::Database::new("sqlite::memory:").await.unwrap();
+        let s = Arc::new(Self {
+            database_url: None,
+            database: sync::RwLock::new(Some(db)),
+            service: sync::RwLock::new(None),
+            instances: sync::RwLock::new(HashMap::new()),
+            http_server: sync::Mutex::new(None),
+            temp_dir: data_dir.join("tmp"),
+            data_dir,
+            exit_token: CancellationToken::new(),
+            metrics: super::Metrics::default(),
+        });
+        let service = service::Service::new(s.clone(), database::DEFAULT_INSTANCE.to_string());
+        *s.service.write().unwrap() = Some(service);
+        s
+    }
+
     pub(crate) fn get_database(&self) -> Database {
         self.database.read().unwrap().clone().unwrap()
     }
@@ -171,6 +224,42 @@ impl Manager {
         self.service.read().unwrap().clone().unwrap()
     }
 
+    // Get the service of an instance, "default" is the legacy service
+    pub(crate) fn get_instance_service(&self, name: &str) -> Option<Service> {
+        if name == database::DEFAULT_INSTANCE {
+            return Some(self.get_service());
+        }
+        self.instances.read().unwrap().get(name).cloned()
+    }
+
+    // The legacy service first, then the named instances by name
+    pub(crate) fn list_services(&self) -> Vec<Service> {
+        let mut instances = self
+            .instances
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, service)| (name.clone(), service.clone()))
+            .collect::<Vec<_>>();
+        instances.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut services = vec![self.get_service()];
+        services.extend(instances.into_iter().map(|(_, service)| service));
+        services
+    }
+
+    pub(crate) fn add_instance(self: &Arc<Self>, name: String) -> Service {
+        let service = service::Service::new(self.clone(), name.clone());
+        self.instances
+            .write()
+            .unwrap()
+            .insert(name, service.clone());
+        service
+    }
+
+    pub(crate) fn remove_instance(&self, name: &str) -> Option<Service> {
+        self.instances.write().unwrap().remove(name)
+    }
+
     pub(crate) fn get_data_dir_path(&self) -> &PathBuf {
         &self.data_dir
     }
@@ -179,6 +268,10 @@ impl Manager {
         &self.temp_dir
     }
 
+    pub(crate) fn get_metrics(&self) -> &super::Metrics {
+        &self.metrics
+    }
+
     pub(crate) fn request_exit(&self) {
         self.exit_token.cancel();
     }
@@ -0,0 +1,333 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{self, atomic::Ordering},
+};
+
+// Manager side counters, since the manager started
+#[derive(Default)]
+pub(crate) struct Metrics {
+    api_requests: sync::Mutex<BTreeMap<(String, u16), u64>>, // (method, status) -> count
+    script_runs: sync::Mutex<BTreeMap<String, (u64, u64)>>,  // script -> (runs, failures)
+}
+
+impl Metrics {
+    pub(crate) fn add_api_request(&self, method: &http::Method, status: http::StatusCode) {
+        *self
+            .api_requests
+            .lock()
+            .unwrap()
+            .entry((method.to_string(), status.as_u16()))
+            .or_default() += 1;
+    }
+
+    // label: "before start script" is counted as script="before_start"
+    pub(crate) fn add_script_run(&self, label: &str, ok: bool) {
+        let script = label.trim_end_matches(" script").replace(' ', "_");
+        let mut script_runs = self.script_runs.lock().unwrap();
+        let (runs, failures) = script_runs.entry(script).or_default();
+        *runs += 1;
+        if !ok {
+            *failures += 1;
+        }
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// Prometheus text format (0.0.4) of the status of all instances and the manager counters
+pub(crate) fn render_metrics(manager: &super::Manager) -> String {
+    let mut out = String::new();
+    let services = manager.list_services();
+    // Status: (name, type, help, value)
+    type StatusMetric = (
+        &'static str,
+        &'static str,
+        &'static str,
+        fn(&crate::service::Status) -> f64,
+    );
+    let status_metrics: [StatusMetric; 15] = [
+        (
+            "boxmgr_service_running",
+            "gauge",
+            "Whether the core is running",
+            |s| s.is_running.load(Ordering::Relaxed) as u8 as f64,
+        ),
+        (
+            "boxmgr_service_restarting",
+            "gauge",
+            "Whether the core waits for the restart backoff",
+            |s| s.is_restarting.load(Ordering::Relaxed) as u8 as f64,
+        ),
+        (
+            "boxmgr_service_restart_attempts",
+            "gauge",
+            "Consecutive restart attempts of the restart policy",
+            |s| s.restart_count.load(Ordering::Relaxed) as f64,
+        ),
+        (
+            "boxmgr_service_restarts_total",
+            "counter",
+            "Restarts of the core, automatic or requested",
+            |s| s.restart_total.load(Ordering::Relaxed) as f64,
+        ),
+        (
+            "boxmgr_service_stats_live",
+            "gauge",
+            "Whether traffic, speed and memory are streamed from the clash api",
+            |s| s.is_stats_live() as u8 as f64,
+        ),
+        (
+            "boxmgr_upload_bytes_total",
+            "counter",
+            "Uploaded bytes since the core started",
+            |s| s.upload_traffic.load(Ordering::Relaxed) as f64,
+        ),
+        (
+            "boxmgr_download_bytes_total",
+            "counter",
+            "Downloaded bytes since the core started",
+            |s| s.download_traffic.load(Ordering::Relaxed) as f64,
+        ),
+        (
+            "boxmgr_upload_speed_bytes",
+            "gauge",
+            "Upload speed in bytes per second",
+            |s| s.upload_speed.load(Ordering::Relaxed) as f64,
+        ),
+        (
+            "boxmgr_download_speed_bytes",
+            "gauge",
+            "Download speed in bytes per second",
+            |s| s.download_speed.load(Ordering::Relaxed) as f64,
+        ),
+        (
+            "boxmgr_memory_bytes",
+            "gauge",
+            "Memory in use reported by the clash api",
+            |s| s.memory_usage.load(Ordering::Relaxed) as f64,
+        ),
+        (
+            "boxmgr_connections",
+            "gauge",
+            "Open connections of the core",
+            |s| s.connection_count.load(Ordering::Relaxed) as f64,
+        ),
+        (
+            "boxmgr_process_cpu_usage_percent",
+            "gauge",
+            "CPU usage of the core process",
+            |s| s.process_cpu_usage.load(Ordering::Relaxed) as f64 / 100.0,
+        ),
+        (
+            "boxmgr_process_resident_memory_bytes",
+            "gauge",
+            "Resident memory of the core process",
+            |s| s.process_memory.load(Ordering::Relaxed) as f64,
+        ),
+        (
+            "boxmgr_process_threads",
+            "gauge",
+            "Threads of the core process",
+            |s| s.process_threads.load(Ordering::Relaxed) as f64,
+        ),
+        (
+            "boxmgr_process_open_fds",
+            "gauge",
+            "Open file descriptors of the core process",
+            |s| s.process_fds.load(Ordering::Relaxed) as f64,
+        ),
+    ];
+    for (name, kind, help, value) in status_metrics {
+        write_family(&mut out, name, kind, help);
+        for service in &services {
+            let (_, status) = service.get_status();
+            let _ = writeln!(
+                out,
+                "{}{{instance=\"{}\"}} {}",
+                name,
+                escape_label(service.get_name()),
+                value(status)
+            );
+        }
+    }
+    write_family(
+        &mut out,
+        "boxmgr_service_info",
+        "gauge",
+        "State, running config and core version of the service",
+    );
+    for service in &services {
+        let (_, status) = service.get_status();
+        let _ = writeln!(
+            out,
+            "boxmgr_service_info{{instance=\"{}\",state=\"{}\",config=\"{}\",core_version=\"{}\"}} 1",
+            escape_label(service.get_name()),
+            status.get_state().as_str(),
+            escape_label(&status.running_config.read().unwrap()),
+            escape_label(&status.core_version.read().unwrap()),
+        );
+    }
+    // Manager
+    let metrics = manager.get_metrics();
+    write_family(
+        &mut out,
+        "boxmgr_api_requests_total",
+        "counter",
+        "Requests to the manager api",
+    );
+    for ((method, status), count) in metrics.api_requests.lock().unwrap().iter() {
+        let _ = writeln!(
+            out,
+            "boxmgr_api_requests_total{{method=\"{}\",status=\"{}\"}} {}",
+            escape_label(method),
+            status,
+            count
+        );
+    }
+    let script_runs = metrics.script_runs.lock().unwrap().clone();
+    write_family(
+        &mut out,
+        "boxmgr_script_runs_total",
+        "counter",
+        "Runs of the start and close scripts",
+    );
+    for (script, (runs, _)) in &script_runs {
+        let _ = writeln!(
+            out,
+            "boxmgr_script_runs_total{{script=\"{}\"}} {}",
+            escape_label(script),
+            runs
+        );
+    }
+    write_family(
+        &mut out,
+        "boxmgr_script_failures_total",
+        "counter",
+        "Runs of the start and close scripts which failed or exited with failure",
+    );
+    for (script, (_, failures)) in &script_runs {
+        let _ = writeln!(
+            out,
+            "boxmgr_script_failures_total{{script=\"{}\"}} {}",
+            escape_label(script),
+            failures
+        );
+    }
+    write_family(
+        &mut out,
+        "boxmgr_db_errors_total",
+        "counter",
+        "Failed database statements",
+    );
+    let _ = writeln!(
+        out,
+        "boxmgr_db_errors_total {}",
+        manager.get_database().error_count()
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("hk"), "hk");
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[tokio::test]
+    async fn test_render_metrics() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = super::super::Manager::new_test(dir.path().to_path_buf()).await;
+        let service = manager.add_instance("hk \"1\"".to_string());
+        let (_, status) = service.get_status();
+        status.is_running.store(true, Ordering::Relaxed);
+        status.upload_traffic.store(1024, Ordering::Relaxed);
+        status.process_cpu_usage.store(1250, Ordering::Relaxed);
+        *status.running_config.write().unwrap() = "main".to_string();
+        let metrics = manager.get_metrics();
+        metrics.add_api_request(&http::Method::GET, http::StatusCode::OK);
+        metrics.add_api_request(&http::Method::GET, http::StatusCode::OK);
+        metrics.add_script_run("before start script", true);
+        metrics.add_script_run("before start script", false);
+
+        let out = render_metrics(&manager);
+        let lines = out.lines().collect::<Vec<_>>();
+        let family = |name: &str| {
+            lines
+                .iter()
+                .filter(|l| {
+                    l.starts_with(&format!("{}{{", name)) || l.starts_with(&format!("{} ", name))
+                })
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        assert!(lines.contains(&"# HELP boxmgr_service_running Whether the core is running"));
+        assert!(lines.contains(&"# TYPE boxmgr_service_running gauge"));
+        assert!(lines.contains(&"# TYPE boxmgr_upload_bytes_total counter"));
+        // One sample per instance, the default one first
+        assert_eq!(
+            family("boxmgr_service_running"),
+            vec![
+                "boxmgr_service_running{instance=\"default\"} 0",
+                "boxmgr_service_running{instance=\"hk \\\"1\\\"\"} 1",
+            ]
+        );
+        assert_eq!(
+            family("boxmgr_upload_bytes_total"),
+            vec![
+                "boxmgr_upload_bytes_total{instance=\"default\"} 0",
+                "boxmgr_upload_bytes_total{instance=\"hk \\\"1\\\"\"} 1024",
+            ]
+        );
+        assert_eq!(
+            family("boxmgr_process_cpu_usage_percent")[1],
+            "boxmgr_process_cpu_usage_percent{instance=\"hk \\\"1\\\"\"} 12.5"
+        );
+        assert_eq!(
+            family("boxmgr_service_info")[1],
+            "boxmgr_service_info{instance=\"hk \\\"1\\\"\",state=\"stopped\",config=\"main\",core_version=\"\"} 1"
+        );
+        assert_eq!(
+            family("boxmgr_api_requests_total"),
+            vec!["boxmgr_api_requests_total{method=\"GET\",status=\"200\"} 2"]
+        );
+        assert_eq!(
+            family("boxmgr_script_runs_total"),
+            vec!["boxmgr_script_runs_total{script=\"before_start\"} 2"]
+        );
+        assert_eq!(
+            family("boxmgr_script_failures_total"),
+            vec!["boxmgr_script_failures_total{script=\"before_start\"} 1"]
+        );
+        assert_eq!(
+            family("boxmgr_db_errors_total"),
+            vec!["boxmgr_db_errors_total 0"]
+        );
+        // Every sample belongs to a family declared before it
+        let mut declared = Vec::new();
+        for line in &lines {
+            match line.strip_prefix("# TYPE ") {
+                Some(v) => declared.push(v.split(' ').next().unwrap()),
+                None if line.starts_with('#') => {}
+                None => {
+                    let name = line.split(['{', ' ']).next().unwrap();
+                    assert_eq!(declared.last(), Some(&name), "{}", line);
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,10 @@
+mod dashboard;
 mod http;
+mod metrics;
 
 pub mod manager;
 
+pub(crate) use dashboard::*;
 pub(crate) use http::*;
 pub use manager::*;
+pub(crate) use metrics::*;
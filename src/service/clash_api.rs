@@ -1,4 +1,8 @@
-use std::{fmt::Debug, net::SocketAddr};
+use std::{
+    fmt::Debug,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
 
 use futures_util::{Future, StreamExt};
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
@@ -12,7 +16,7 @@ pub(crate) enum ClashAPIType {
 
 impl ClashAPIType {
     pub(crate) fn create_url(&self, listen: SocketAddr, secret: Option<String>) -> String {
-        let mut url = format!("ws://{}", listen);
+        let mut url = format!("ws://{}", connect_addr(listen));
         match self {
             Self::Traffic => url.push_str("/connections"),
             Self::Speed => url.push_str("/traffic"),
@@ -25,67 +29,377 @@ impl ClashAPIType {
         }
     }
 
-    pub(crate) async fn handle<P, T, F, Fut>(
+    // Keep the stream connected until cancelled, reconnect with backoff on any error
+    pub(crate) async fn handle<P, T, F, Fut, L>(
         &self,
         listen: SocketAddr,
         secret: Option<String>,
         token: CancellationToken,
         params: P,
         callback: F,
+        live: L,
     ) where
         P: Clone + Send,
         T: serde::de::DeserializeOwned + Debug + Send,
         F: Fn(P, T) -> Fut,
         Fut: Future<Output = ()>,
+        L: Fn(P, bool),
     {
-        let req = self
-            .create_url(listen, secret)
-            .into_client_request()
-            .unwrap();
-        let mut stream = match tokio::select! {
-          _ = token.cancelled() => return,
-          res = tokio_tungstenite::connect_async(req) => res,
-        } {
-            Ok((stream, _)) => stream,
-            Err(e) => {
-                log::error!("failed to connect to clash api: {}", e);
-                return;
-            }
-        };
+        const BACKOFF_MIN: Duration = Duration::from_secs(1);
+        const BACKOFF_MAX: Duration = Duration::from_secs(30);
+        // The streams push every second, a silent stream is treated as broken
+        const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+        let url = self.create_url(listen, secret);
+        let mut backoff = BACKOFF_MIN;
         loop {
-            tokio::select! {
-              _ = token.cancelled() => break,
-              res = stream.next() => {
-                match res {
-                  Some(Ok(msg)) => {
-                    match msg {
-                      tokio_tungstenite::tungstenite::Message::Text(text) => {
-                        if let Ok(data) = serde_json::from_str::<'_, T>(&text) {
-                          tokio::select! {
-                            _ = token.cancelled() => break,
-                            _ = callback(params.clone(), data) => {}
+            let req = url.as_str().into_client_request().unwrap();
+            match tokio::select! {
+              _ = token.cancelled() => return,
+              res = tokio_tungstenite::connect_async(req) => res,
+            } {
+                Ok((mut stream, _)) => {
+                    backoff = BACKOFF_MIN;
+                    live(params.clone(), true);
+                    let cancelled = loop {
+                        tokio::select! {
+                          _ = token.cancelled() => break true,
+                          res = tokio::time::timeout(IDLE_TIMEOUT, stream.next()) => {
+                            match res {
+                              Ok(Some(Ok(tokio_tungstenite::tungstenite::Message::Text(text)))) => {
+                                if let Ok(data) = serde_json::from_str::<'_, T>(&text) {
+                                  tokio::select! {
+                                    _ = token.cancelled() => break true,
+                                    _ = callback(params.clone(), data) => {}
+                                  }
+                                }
+                              }
+                              Ok(Some(Ok(_))) => {}
+                              Ok(Some(Err(e))) => {
+                                log::warn!("failed to receive message from clash api: {}", e);
+                                break false;
+                              }
+                              Ok(None) => {
+                                log::warn!("clash api stream is closed");
+                                break false;
+                              }
+                              Err(_) => {
+                                log::warn!("clash api stream is idle for {}s", IDLE_TIMEOUT.as_secs());
+                                break false;
+                              }
+                            }
                           }
                         }
-                      }
-                      _ => {}
+                    };
+                    live(params.clone(), false);
+                    // The peer may be gone, do not wait for the close handshake
+                    let _ = tokio::time::timeout(BACKOFF_MIN, stream.close(None)).await;
+                    if cancelled {
+                        return;
                     }
-                  }
-                  Some(Err(e)) => {
-                    log::error!("failed to receive message from clash api: {}", e);
-                    break;
-                  }
-                  None => {}
                 }
-              }
+                Err(e) => {
+                    log::warn!(
+                        "failed to connect to clash api: {}, retry in {}s",
+                        e,
+                        backoff.as_secs()
+                    );
+                }
+            }
+            tokio::select! {
+              _ = token.cancelled() => return,
+              _ = tokio::time::sleep(backoff) => {}
             }
+            backoff = (backoff * 2).min(BACKOFF_MAX);
+        }
+    }
+}
+
+// Clash API may listen on an unspecified address, connect to loopback instead
+pub(crate) fn connect_addr(listen: SocketAddr) -> SocketAddr {
+    match listen.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => {
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), listen.port())
+        }
+        IpAddr::V6(ip) if ip.is_unspecified() => {
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), listen.port())
+        }
+        _ => listen,
+    }
+}
+
+// The error message of the clash api on failure
+async fn check_response(response: reqwest::Response) -> Result<reqwest::Response, String> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    Err(response
+        .json::<ClashAPIErrorResult>()
+        .await
+        .map(|e| e.message)
+        .unwrap_or_else(|_| status.to_string()))
+}
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+pub(crate) struct ClashAPIClient {
+    listen: SocketAddr,
+    secret: Option<String>,
+    client: reqwest::Client,
+}
+
+impl ClashAPIClient {
+    pub(crate) fn new(listen: SocketAddr, secret: Option<String>) -> Self {
+        Self {
+            listen,
+            secret,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("http://{}{}", connect_addr(self.listen), path);
+        let mut builder = self.client.request(method, url);
+        if let Some(secret) = &self.secret {
+            builder = builder.bearer_auth(secret);
+        }
+        builder
+    }
+
+    // Path segments are percent encoded, outbound tags may contain any character
+    fn request_segments(
+        &self,
+        method: reqwest::Method,
+        segments: &[&str],
+    ) -> reqwest::RequestBuilder {
+        let mut url = reqwest::Url::parse(&format!("http://{}", connect_addr(self.listen)))
+            .expect("socket address is a valid url host");
+        url.path_segments_mut()
+            .expect("http url has a path")
+            .extend(segments);
+        let mut builder = self.client.request(method, url);
+        if let Some(secret) = &self.secret {
+            builder = builder.bearer_auth(secret);
+        }
+        builder
+    }
+
+    // Any clash api path, used by the reverse proxy
+    pub(crate) fn forward_request(
+        &self,
+        method: reqwest::Method,
+        path_and_query: &str,
+    ) -> reqwest::RequestBuilder {
+        self.request(method, path_and_query)
+    }
+
+    // Websocket request of any clash api path, authorized by header
+    pub(crate) fn forward_websocket_request(
+        &self,
+        path_and_query: &str,
+    ) -> Result<tokio_tungstenite::tungstenite::handshake::client::Request, String> {
+        let mut req = format!("ws://{}{}", connect_addr(self.listen), path_and_query)
+            .into_client_request()
+            .map_err(|e| e.to_string())?;
+        if let Some(secret) = &self.secret {
+            let value = format!("Bearer {}", secret)
+                .parse()
+                .map_err(|e: http::header::InvalidHeaderValue| e.to_string())?;
+            req.headers_mut().insert(http::header::AUTHORIZATION, value);
+        }
+        Ok(req)
+    }
+
+    // Send a request, the error message of the clash api on failure
+    async fn send(builder: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        check_response(builder.send().await.map_err(|e| e.to_string())?).await
+    }
+
+    async fn send_json<T: serde::de::DeserializeOwned>(
+        builder: reqwest::RequestBuilder,
+    ) -> Result<T, String> {
+        Self::send(builder)
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    pub(crate) async fn version(&self, timeout: Duration) -> Result<ClashAPIVersionResult, String> {
+        Self::send_json(
+            self.request(reqwest::Method::GET, "/version")
+                .timeout(timeout),
+        )
+        .await
+    }
+
+    pub(crate) async fn connections(&self) -> Result<Vec<ClashAPIConnection>, String> {
+        let result: ClashAPITrafficResult = Self::send_json(
+            self.request(reqwest::Method::GET, "/connections")
+                .timeout(REQUEST_TIMEOUT),
+        )
+        .await?;
+        Ok(result.connections.unwrap_or_default())
+    }
+
+    pub(crate) async fn close_connection(&self, id: &str) -> Result<(), String> {
+        Self::send(
+            self.request(reqwest::Method::DELETE, &format!("/connections/{}", id))
+                .timeout(REQUEST_TIMEOUT),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    pub(crate) async fn close_all_connections(&self) -> Result<(), String> {
+        Self::send(
+            self.request(reqwest::Method::DELETE, "/connections")
+                .timeout(REQUEST_TIMEOUT),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    pub(crate) async fn proxies(
+        &self,
+    ) -> Result<std::collections::HashMap<String, ClashAPIProxy>, String> {
+        let result: ClashAPIProxiesResult = Self::send_json(
+            self.request(reqwest::Method::GET, "/proxies")
+                .timeout(REQUEST_TIMEOUT),
+        )
+        .await?;
+        Ok(result.proxies)
+    }
+
+    // Only selector outbounds accept a selection
+    pub(crate) async fn select_proxy(&self, group: &str, name: &str) -> Result<(), String> {
+        Self::send(
+            self.request_segments(reqwest::Method::PUT, &["proxies", group])
+                .json(&serde_json::json!({ "name": name }))
+                .timeout(REQUEST_TIMEOUT),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    // Delay (ms) of one outbound, the error message of the clash api on failure
+    pub(crate) async fn proxy_delay(
+        &self,
+        name: &str,
+        url: &str,
+        timeout: u64,
+    ) -> Result<u32, String> {
+        let result: ClashAPIDelayResult = self
+            .delay_request(&["proxies", name, "delay"], url, timeout)
+            .await?;
+        Ok(result.delay)
+    }
+
+    // Delays (ms) of the group members, 0 if failed
+    pub(crate) async fn group_delay(
+        &self,
+        name: &str,
+        url: &str,
+        timeout: u64,
+    ) -> Result<std::collections::HashMap<String, u32>, String> {
+        self.delay_request(&["group", name, "delay"], url, timeout)
+            .await
+    }
+
+    async fn delay_request<T: serde::de::DeserializeOwned>(
+        &self,
+        segments: &[&str],
+        url: &str,
+        timeout: u64, // ms
+    ) -> Result<T, String> {
+        Self::send_json(
+            self.request_segments(reqwest::Method::GET, segments)
+                .query(&[("url", url), ("timeout", &timeout.to_string())])
+                .timeout(Duration::from_millis(timeout) + REQUEST_TIMEOUT),
+        )
+        .await
+    }
+
+    pub(crate) async fn configs(&self) -> Result<ClashAPIConfigsResult, String> {
+        Self::send_json(
+            self.request(reqwest::Method::GET, "/configs")
+                .timeout(REQUEST_TIMEOUT),
+        )
+        .await
+    }
+
+    // The core ignores a mode which is not in its mode list
+    pub(crate) async fn set_mode(&self, mode: &str) -> Result<(), String> {
+        Self::send(
+            self.request(reqwest::Method::PATCH, "/configs")
+                .json(&serde_json::json!({ "mode": mode }))
+                .timeout(REQUEST_TIMEOUT),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    // Answer of the core's dns, as returned by the clash api
+    pub(crate) async fn dns_query(
+        &self,
+        name: &str,
+        query_type: &str,
+    ) -> Result<serde_json::Value, String> {
+        Self::send_json(
+            self.request(reqwest::Method::GET, "/dns/query")
+                .query(&[("name", name), ("type", query_type)])
+                .timeout(REQUEST_TIMEOUT),
+        )
+        .await
+    }
+
+    pub(crate) async fn flush_dns_cache(&self) -> Result<(), String> {
+        self.flush_cache("/cache/dns/flush").await
+    }
+
+    pub(crate) async fn flush_fakeip_cache(&self) -> Result<(), String> {
+        self.flush_cache("/cache/fakeip/flush").await
+    }
+
+    async fn flush_cache(&self, path: &str) -> Result<(), String> {
+        Self::send(
+            self.request(reqwest::Method::POST, path)
+                .timeout(REQUEST_TIMEOUT),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    // Poll /version until the clash api answers
+    pub(crate) async fn wait_ready(&self) {
+        const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+        const PROBE_INTERVAL: Duration = Duration::from_millis(200);
+
+        loop {
+            match self.version(PROBE_TIMEOUT).await {
+                Ok(v) => {
+                    log::debug!("service: clash api is ready: version: {}", v.version);
+                    return;
+                }
+                Err(e) => {
+                    log::trace!("service: clash api is not ready: {}", e);
+                }
+            }
+            tokio::time::sleep(PROBE_INTERVAL).await;
         }
-        let _ = stream.close(None).await;
     }
 }
 
+#[derive(Debug, serde::Deserialize)]
+pub(crate) struct ClashAPIVersionResult {
+    pub(crate) version: String,
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub(crate) struct ClashAPITrafficResult {
-    pub(crate) connections: Option<Vec<serde_json::Value>>,
+    pub(crate) connections: Option<Vec<ClashAPIConnection>>,
     #[serde(rename = "downloadTotal")]
     pub(crate) download_traffic: u64,
     #[serde(rename = "uploadTotal")]
@@ -105,3 +419,96 @@ pub(crate) struct ClashAPIMemoryResult {
     #[serde(rename = "inuse")]
     pub(crate) memory: u64,
 }
+
+// Active connection of the /connections stream, serialized in snake case
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub(crate) struct ClashAPIConnection {
+    pub(crate) id: String,
+    #[serde(default)]
+    pub(crate) metadata: ClashAPIConnectionMetadata,
+    #[serde(default)]
+    pub(crate) upload: u64, // B
+    #[serde(default)]
+    pub(crate) download: u64, // B
+    #[serde(default)]
+    pub(crate) start: String, // RFC 3339
+    #[serde(default)]
+    pub(crate) chains: Vec<String>, // last outbound first
+    #[serde(default)]
+    pub(crate) rule: String,
+    #[serde(default, rename(deserialize = "rulePayload"))]
+    pub(crate) rule_payload: String,
+}
+
+impl ClashAPIConnection {
+    // Unix timestamp (ms), 0 if the start time is invalid
+    pub(crate) fn start_time(&self) -> i64 {
+        chrono::DateTime::parse_from_rfc3339(&self.start)
+            .map(|t| t.timestamp_millis())
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct ClashAPIConnectionMetadata {
+    pub(crate) network: String,
+    #[serde(rename = "type")]
+    pub(crate) inbound_type: String,
+    #[serde(rename(deserialize = "sourceIP"))]
+    pub(crate) source_ip: String,
+    #[serde(rename(deserialize = "sourcePort"))]
+    pub(crate) source_port: String,
+    #[serde(rename(deserialize = "destinationIP"))]
+    pub(crate) destination_ip: String,
+    #[serde(rename(deserialize = "destinationPort"))]
+    pub(crate) destination_port: String,
+    pub(crate) host: String,
+    #[serde(rename(deserialize = "dnsMode"))]
+    pub(crate) dns_mode: String,
+    #[serde(rename(deserialize = "processPath"))]
+    pub(crate) process_path: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub(crate) struct ClashAPIProxiesResult {
+    pub(crate) proxies: std::collections::HashMap<String, ClashAPIProxy>,
+}
+
+// Outbound of /proxies, groups have members in all
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub(crate) struct ClashAPIProxy {
+    pub(crate) name: String,
+    #[serde(rename = "type")]
+    pub(crate) proxy_type: String,
+    #[serde(default)]
+    pub(crate) now: String, // selected member of groups
+    #[serde(default)]
+    pub(crate) all: Vec<String>,
+    #[serde(default)]
+    pub(crate) history: Vec<ClashAPIDelayHistory>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub(crate) struct ClashAPIDelayHistory {
+    pub(crate) time: String, // RFC 3339
+    pub(crate) delay: u64,   // ms, 0 if failed
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub(crate) struct ClashAPIDelayResult {
+    pub(crate) delay: u32, // ms
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub(crate) struct ClashAPIConfigsResult {
+    pub(crate) mode: String,
+    // Missing on cores without mode switching
+    #[serde(default, rename(deserialize = "mode-list"))]
+    pub(crate) mode_list: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub(crate) struct ClashAPIErrorResult {
+    pub(crate) message: String,
+}
@@ -0,0 +1,271 @@
+use std::collections::{HashMap, HashSet};
+
+use super::ClashAPIConnection;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConnectionSort {
+    Traffic, // upload + download
+    Upload,
+    Download,
+    Age,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConnectionOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+// Filters are case insensitive, empty ones match everything
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct ConnectionQuery {
+    pub(crate) host: Option<String>, // substring of the host or destination ip
+    pub(crate) source_ip: Option<String>, // exact
+    pub(crate) outbound: Option<String>, // any outbound of the chain
+    pub(crate) rule: Option<String>, // substring of the rule or its payload
+    pub(crate) sort: Option<ConnectionSort>,
+    pub(crate) order: ConnectionOrder,
+}
+
+impl ConnectionQuery {
+    pub(crate) fn is_filtered(&self) -> bool {
+        [&self.host, &self.source_ip, &self.outbound, &self.rule]
+            .iter()
+            .any(|f| matches!(f, Some(s) if !s.is_empty()))
+    }
+
+    pub(crate) fn matches(&self, conn: &ClashAPIConnection) -> bool {
+        fn contains(s: &str, pattern: &str) -> bool {
+            s.to_lowercase().contains(&pattern.to_lowercase())
+        }
+
+        if let Some(host) = self.host.as_deref().filter(|s| !s.is_empty()) {
+            if !contains(&conn.metadata.host, host)
+                && !contains(&conn.metadata.destination_ip, host)
+            {
+                return false;
+            }
+        }
+        if let Some(source_ip) = self.source_ip.as_deref().filter(|s| !s.is_empty()) {
+            if !conn.metadata.source_ip.eq_ignore_ascii_case(source_ip) {
+                return false;
+            }
+        }
+        if let Some(outbound) = self.outbound.as_deref().filter(|s| !s.is_empty()) {
+            if !conn.chains.iter().any(|c| c.eq_ignore_ascii_case(outbound)) {
+                return false;
+            }
+        }
+        if let Some(rule) = self.rule.as_deref().filter(|s| !s.is_empty()) {
+            if !contains(&conn.rule, rule) && !contains(&conn.rule_payload, rule) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Age is the time since start, so descending puts the oldest first
+    pub(crate) fn apply(&self, conns: &[ClashAPIConnection]) -> Vec<ClashAPIConnection> {
+        let mut conns = conns
+            .iter()
+            .filter(|c| self.matches(c))
+            .cloned()
+            .collect::<Vec<_>>();
+        if let Some(sort) = self.sort {
+            match sort {
+                ConnectionSort::Traffic => conns.sort_by_key(|c| c.upload + c.download),
+                ConnectionSort::Upload => conns.sort_by_key(|c| c.upload),
+                ConnectionSort::Download => conns.sort_by_key(|c| c.download),
+                ConnectionSort::Age => conns.sort_by_key(|c| std::cmp::Reverse(c.start_time())),
+            }
+            if self.order == ConnectionOrder::Desc {
+                conns.reverse();
+            }
+        }
+        conns
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct ConnectionUpdate {
+    pub(crate) id: String,
+    pub(crate) upload: u64,
+    pub(crate) download: u64,
+}
+
+// Changes between two filtered snapshots, the first diff adds everything
+#[derive(Debug, Default, serde::Serialize)]
+pub(crate) struct ConnectionDiff {
+    pub(crate) added: Vec<ClashAPIConnection>,
+    pub(crate) updated: Vec<ConnectionUpdate>,
+    pub(crate) removed: Vec<String>,
+}
+
+impl ConnectionDiff {
+    pub(crate) fn new(previous: &[ClashAPIConnection], current: &[ClashAPIConnection]) -> Self {
+        let previous_map = previous
+            .iter()
+            .map(|c| (c.id.as_str(), c))
+            .collect::<HashMap<_, _>>();
+        let mut diff = Self::default();
+        for conn in current {
+            match previous_map.get(conn.id.as_str()) {
+                None => diff.added.push(conn.clone()),
+                Some(prev) if prev.upload != conn.upload || prev.download != conn.download => {
+                    diff.updated.push(ConnectionUpdate {
+                        id: conn.id.clone(),
+                        upload: conn.upload,
+                        download: conn.download,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        let current_ids = current
+            .iter()
+            .map(|c| c.id.as_str())
+            .collect::<HashSet<_>>();
+        diff.removed = previous
+            .iter()
+            .filter(|c| !current_ids.contains(c.id.as_str()))
+            .map(|c| c.id.clone())
+            .collect();
+        diff
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn(id: &str, host: &str, upload: u64, download: u64, start: &str) -> ClashAPIConnection {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "metadata": {
+                "host": host,
+                "sourceIP": "192.168.1.2",
+                "destinationIP": "1.1.1.1",
+            },
+            "upload": upload,
+            "download": download,
+            "start": start,
+            "chains": ["a", "Proxy"],
+            "rule": "domain_suffix=example.com",
+            "rulePayload": "",
+        }))
+        .unwrap()
+    }
+
+    fn ids(conns: &[ClashAPIConnection]) -> Vec<&str> {
+        conns.iter().map(|c| c.id.as_str()).collect()
+    }
+
+    fn sample() -> Vec<ClashAPIConnection> {
+        vec![
+            conn("1", "www.example.com", 10, 100, "2024-01-01T00:00:02Z"),
+            conn("2", "api.github.com", 300, 0, "2024-01-01T00:00:01Z"),
+            conn("3", "", 20, 50, "2024-01-01T00:00:03Z"),
+        ]
+    }
+
+    #[test]
+    fn test_apply_filter() {
+        let conns = sample();
+        let query = ConnectionQuery {
+            host: Some("EXAMPLE".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&query.apply(&conns)), vec!["1"]);
+        // The destination ip is matched when the host is empty
+        let query = ConnectionQuery {
+            host: Some("1.1.1".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&query.apply(&conns)), vec!["1", "2", "3"]);
+        let query = ConnectionQuery {
+            outbound: Some("proxy".to_string()),
+            source_ip: Some("192.168.1.3".to_string()),
+            ..Default::default()
+        };
+        assert!(query.apply(&conns).is_empty());
+        let query = ConnectionQuery {
+            host: Some(String::new()),
+            rule: Some("example".to_string()),
+            ..Default::default()
+        };
+        assert!(query.is_filtered());
+        assert_eq!(query.apply(&conns).len(), 3);
+        assert!(!ConnectionQuery::default().is_filtered());
+    }
+
+    #[test]
+    fn test_apply_sort() {
+        let conns = sample();
+        let query = |sort, order| ConnectionQuery {
+            sort: Some(sort),
+            order,
+            ..Default::default()
+        };
+        let desc = ConnectionOrder::Desc;
+        let asc = ConnectionOrder::Asc;
+        assert_eq!(
+            ids(&query(ConnectionSort::Traffic, desc).apply(&conns)),
+            vec!["2", "1", "3"]
+        );
+        assert_eq!(
+            ids(&query(ConnectionSort::Upload, asc).apply(&conns)),
+            vec!["1", "3", "2"]
+        );
+        assert_eq!(
+            ids(&query(ConnectionSort::Download, desc).apply(&conns)),
+            vec!["1", "3", "2"]
+        );
+        // Descending age puts the oldest first
+        assert_eq!(
+            ids(&query(ConnectionSort::Age, desc).apply(&conns)),
+            vec!["2", "1", "3"]
+        );
+        assert_eq!(
+            ids(&query(ConnectionSort::Age, asc).apply(&conns)),
+            vec!["3", "1", "2"]
+        );
+        // Unsorted keeps the order of the core
+        assert_eq!(
+            ids(&ConnectionQuery::default().apply(&conns)),
+            vec!["1", "2", "3"]
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let previous = sample();
+        let diff = ConnectionDiff::new(&[], &previous);
+        assert_eq!(ids(&diff.added), vec!["1", "2", "3"]);
+        assert!(diff.updated.is_empty() && diff.removed.is_empty());
+
+        let current = vec![
+            conn("1", "www.example.com", 10, 100, "2024-01-01T00:00:02Z"),
+            conn("3", "", 25, 50, "2024-01-01T00:00:03Z"),
+            conn("4", "new.example.com", 0, 0, "2024-01-01T00:00:04Z"),
+        ];
+        let diff = ConnectionDiff::new(&previous, &current);
+        assert_eq!(ids(&diff.added), vec!["4"]);
+        assert_eq!(
+            diff.updated
+                .iter()
+                .map(|u| (u.id.as_str(), u.upload, u.download))
+                .collect::<Vec<_>>(),
+            vec![("3", 25, 50)]
+        );
+        assert_eq!(diff.removed, vec!["2".to_string()]);
+        assert!(ConnectionDiff::new(&current, &current).is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+use futures_util::{future::BoxFuture, FutureExt, StreamExt};
+
+const DEFAULT_URL: &str = "https://www.gstatic.com/generate_204";
+const DEFAULT_TIMEOUT: u64 = 5000; // ms
+const DEFAULT_CONCURRENCY: usize = 8;
+const MAX_TIMEOUT: u64 = 60000; // ms
+const MAX_CONCURRENCY: usize = 64;
+const MAX_TARGETS: usize = 256;
+
+// Outbounds are tested one by one, a group is tested by the core in one request
+#[derive(Debug, Clone, serde::Deserialize)]
+pub(crate) struct DelayTestOption {
+    #[serde(default)]
+    pub(crate) outbounds: Vec<String>,
+    #[serde(default)]
+    pub(crate) groups: Vec<String>,
+    #[serde(default = "default_url")]
+    pub(crate) url: String,
+    #[serde(default = "default_timeout")]
+    pub(crate) timeout: u64, // ms
+    #[serde(default = "default_concurrency")]
+    pub(crate) concurrency: usize, // requests to the clash api at the same time
+}
+
+fn default_url() -> String {
+    DEFAULT_URL.to_string()
+}
+
+fn default_timeout() -> u64 {
+    DEFAULT_TIMEOUT
+}
+
+fn default_concurrency() -> usize {
+    DEFAULT_CONCURRENCY
+}
+
+impl DelayTestOption {
+    pub(crate) fn check(&self) -> Result<(), String> {
+        let targets = self.outbounds.len() + self.groups.len();
+        if targets == 0 {
+            return Err("no outbound or group to test".to_string());
+        }
+        if targets > MAX_TARGETS {
+            return Err(format!(
+                "too many outbounds and groups, max: {}",
+                MAX_TARGETS
+            ));
+        }
+        if self
+            .outbounds
+            .iter()
+            .chain(self.groups.iter())
+            .any(|name| name.is_empty())
+        {
+            return Err("empty outbound or group".to_string());
+        }
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(format!("invalid url: {}", self.url));
+        }
+        if !(1..=MAX_TIMEOUT).contains(&self.timeout) {
+            return Err(format!("timeout must be between 1 and {}ms", MAX_TIMEOUT));
+        }
+        if !(1..=MAX_CONCURRENCY).contains(&self.concurrency) {
+            return Err(format!(
+                "concurrency must be between 1 and {}",
+                MAX_CONCURRENCY
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Delay (ms) or error message of an outbound
+type DelayResult = (String, Result<u32, String>);
+
+// Group members are included in the results
+pub(crate) async fn run_delay_test(
+    client: &super::ClashAPIClient,
+    option: &DelayTestOption,
+) -> Vec<DelayResult> {
+    let mut tests: Vec<BoxFuture<'_, Vec<DelayResult>>> = Vec::new();
+    for name in option.outbounds.iter() {
+        tests.push(
+            async move {
+                let result = client.proxy_delay(name, &option.url, option.timeout).await;
+                vec![(name.clone(), result)]
+            }
+            .boxed(),
+        );
+    }
+    for name in option.groups.iter() {
+        tests.push(
+            async move {
+                match client.group_delay(name, &option.url, option.timeout).await {
+                    Ok(delays) => {
+                        let mut results = delays
+                            .into_iter()
+                            .map(|(member, delay)| match delay {
+                                0 => (member, Err("delay test failed".to_string())),
+                                delay => (member, Ok(delay)),
+                            })
+                            .collect::<Vec<_>>();
+                        results.sort_by(|a, b| a.0.cmp(&b.0));
+                        results
+                    }
+                    Err(e) => vec![(name.clone(), Err(e))],
+                }
+            }
+            .boxed(),
+        );
+    }
+    futures_util::stream::iter(tests)
+        .buffered(option.concurrency)
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn option(value: serde_json::Value) -> DelayTestOption {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_default() {
+        let option = option(serde_json::json!({"outbounds": ["a"]}));
+        assert_eq!(option.url, DEFAULT_URL);
+        assert_eq!(option.timeout, DEFAULT_TIMEOUT);
+        assert_eq!(option.concurrency, DEFAULT_CONCURRENCY);
+        assert!(option.check().is_ok());
+    }
+
+    #[test]
+    fn test_check_targets() {
+        assert!(option(serde_json::json!({})).check().is_err());
+        assert!(option(serde_json::json!({"groups": ["Proxy"]}))
+            .check()
+            .is_ok());
+        assert!(option(serde_json::json!({"outbounds": ["a", ""]}))
+            .check()
+            .is_err());
+        let outbounds = (0..MAX_TARGETS).map(|i| i.to_string()).collect::<Vec<_>>();
+        assert!(option(serde_json::json!({"outbounds": outbounds}))
+            .check()
+            .is_ok());
+        assert!(
+            option(serde_json::json!({"outbounds": outbounds, "groups": ["Proxy"]}))
+                .check()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_check_bounds() {
+        let check = |key: &str, value: serde_json::Value| {
+            let mut v = serde_json::json!({"outbounds": ["a"]});
+            v[key] = value;
+            option(v).check()
+        };
+        assert!(check("url", "http://cp.cloudflare.com".into()).is_ok());
+        assert!(check("url", "ftp://example.com".into()).is_err());
+        assert!(check("url", "www.gstatic.com/generate_204".into()).is_err());
+        assert!(check("timeout", 1.into()).is_ok());
+        assert!(check("timeout", MAX_TIMEOUT.into()).is_ok());
+        assert!(check("timeout", 0.into()).is_err());
+        assert!(check("timeout", (MAX_TIMEOUT + 1).into()).is_err());
+        assert!(check("concurrency", 1.into()).is_ok());
+        assert!(check("concurrency", MAX_CONCURRENCY.into()).is_ok());
+        assert!(check("concurrency", 0.into()).is_err());
+        assert!(check("concurrency", (MAX_CONCURRENCY + 1).into()).is_err());
+    }
+}
@@ -3,9 +3,26 @@ use std::fmt;
 pub(crate) enum Error {
     ConfigNotSet,
     GetConfigFailed(String),
+    SetActiveConfigFailed(String),
     CorePathNotSet,
     GetCorePathFailed(String),
+    GetLaunchOptionFailed(String),
+    GetProcessLimitFailed(String),
+    GetNetnsFailed(String),
     StartServiceFailed(String),
+    CheckConfigFailed(String),
+    ServiceNotReady(u64, Vec<String>),      // Timeout, Recent Logs
+    ServiceExited(Vec<String>),             // Recent Logs
+    RolledBack(String, String),             // Error, Rollback Config Tag
+    RollbackFailed(String, String, String), // Error, Rollback Config Tag, Rollback Error
+    NoPendingConfirm,
+    ServiceNotRunning,
+    ClashAPIFailed(String),
+    SetProxySelectionFailed(String),
+    SaveDelayTestFailed(String),
+    InvalidClashMode(String),
+    SetClashModeFailed(String),
+    Busy(super::ServiceState), // State
 }
 
 impl fmt::Debug for Error {
@@ -13,9 +30,37 @@ impl fmt::Debug for Error {
         match self {
             Self::ConfigNotSet => write!(f, "config is not set"),
             Self::GetConfigFailed(s) => write!(f, "get config failed: {}", s),
+            Self::SetActiveConfigFailed(s) => write!(f, "set active config failed: {}", s),
             Self::CorePathNotSet => write!(f, "core path is not set"),
             Self::GetCorePathFailed(s) => write!(f, "get core path failed: {}", s),
+            Self::GetLaunchOptionFailed(s) => write!(f, "get launch option failed: {}", s),
+            Self::GetProcessLimitFailed(s) => write!(f, "get process limit failed: {}", s),
+            Self::GetNetnsFailed(s) => write!(f, "get netns failed: {}", s),
             Self::StartServiceFailed(s) => write!(f, "start service failed: {}", s),
+            Self::CheckConfigFailed(s) => write!(f, "check config failed: {}", s),
+            Self::ServiceNotReady(t, logs) => write!(
+                f,
+                "service is not ready after {}s, recent logs:\n{}",
+                t,
+                logs.join("\n")
+            ),
+            Self::ServiceExited(logs) => write!(
+                f,
+                "service exited before ready, recent logs:\n{}",
+                logs.join("\n")
+            ),
+            Self::RolledBack(e, tag) => write!(f, "{}\nrolled back to config: {}", e, tag),
+            Self::RollbackFailed(e, tag, re) => {
+                write!(f, "{}\nroll back to config {} failed: {}", e, tag, re)
+            }
+            Self::NoPendingConfirm => write!(f, "no config is pending confirmation"),
+            Self::ServiceNotRunning => write!(f, "service is not running"),
+            Self::ClashAPIFailed(s) => write!(f, "clash api request failed: {}", s),
+            Self::SetProxySelectionFailed(s) => write!(f, "save proxy selection failed: {}", s),
+            Self::SaveDelayTestFailed(s) => write!(f, "save delay test failed: {}", s),
+            Self::InvalidClashMode(s) => write!(f, "invalid clash mode: {}", s),
+            Self::SetClashModeFailed(s) => write!(f, "save clash mode failed: {}", s),
+            Self::Busy(state) => write!(f, "service is {}, try again later", state),
         }
     }
 }
@@ -25,9 +70,37 @@ impl fmt::Display for Error {
         match self {
             Self::ConfigNotSet => write!(f, "config is not set"),
             Self::GetConfigFailed(s) => write!(f, "get config failed: {}", s),
+            Self::SetActiveConfigFailed(s) => write!(f, "set active config failed: {}", s),
             Self::CorePathNotSet => write!(f, "core path is not set"),
             Self::GetCorePathFailed(s) => write!(f, "get core path failed: {}", s),
+            Self::GetLaunchOptionFailed(s) => write!(f, "get launch option failed: {}", s),
+            Self::GetProcessLimitFailed(s) => write!(f, "get process limit failed: {}", s),
+            Self::GetNetnsFailed(s) => write!(f, "get netns failed: {}", s),
             Self::StartServiceFailed(s) => write!(f, "start service failed: {}", s),
+            Self::CheckConfigFailed(s) => write!(f, "check config failed: {}", s),
+            Self::ServiceNotReady(t, logs) => write!(
+                f,
+                "service is not ready after {}s, recent logs:\n{}",
+                t,
+                logs.join("\n")
+            ),
+            Self::ServiceExited(logs) => write!(
+                f,
+                "service exited before ready, recent logs:\n{}",
+                logs.join("\n")
+            ),
+            Self::RolledBack(e, tag) => write!(f, "{}\nrolled back to config: {}", e, tag),
+            Self::RollbackFailed(e, tag, re) => {
+                write!(f, "{}\nroll back to config {} failed: {}", e, tag, re)
+            }
+            Self::NoPendingConfirm => write!(f, "no config is pending confirmation"),
+            Self::ServiceNotRunning => write!(f, "service is not running"),
+            Self::ClashAPIFailed(s) => write!(f, "clash api request failed: {}", s),
+            Self::SetProxySelectionFailed(s) => write!(f, "save proxy selection failed: {}", s),
+            Self::SaveDelayTestFailed(s) => write!(f, "save delay test failed: {}", s),
+            Self::InvalidClashMode(s) => write!(f, "invalid clash mode: {}", s),
+            Self::SetClashModeFailed(s) => write!(f, "save clash mode failed: {}", s),
+            Self::Busy(state) => write!(f, "service is {}, try again later", state),
         }
     }
 }
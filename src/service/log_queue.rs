@@ -30,6 +30,15 @@ impl<T: Clone> LogQueue<T> {
         let _ = self.sender.send(v);
     }
 
+    pub(crate) fn recent(&self, n: usize) -> Vec<T> {
+        let cache = self.cache.read().unwrap();
+        cache
+            .iter()
+            .skip(cache.len().saturating_sub(n))
+            .cloned()
+            .collect()
+    }
+
     pub(crate) fn subscribe(&self) -> LogQueueListener<T> {
         let receiver = self.sender.subscribe();
         LogQueueListener {
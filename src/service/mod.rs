@@ -1,13 +1,28 @@
 mod clash_api;
+mod connection;
+mod delay_test;
 mod error;
 mod log_queue;
+mod netns;
+mod process_limit;
+mod process_stat;
 mod script;
 mod service;
+mod stat;
 mod state;
 
 use clash_api::*;
+pub(crate) use clash_api::{
+    ClashAPIClient, ClashAPIConfigsResult, ClashAPIConnection, ClashAPIProxy,
+};
+pub(crate) use connection::*;
+pub(crate) use delay_test::*;
 pub(crate) use error::*;
 use log_queue::*;
+use netns::*;
+use process_limit::*;
+use process_stat::*;
 pub(crate) use script::*;
 pub(crate) use service::*;
+use stat::*;
 use state::*;
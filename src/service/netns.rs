@@ -0,0 +1,151 @@
+use std::net::SocketAddr;
+
+use tokio::process::Command;
+
+use crate::database;
+
+// Create the namespace and the veth pair if missing, existing interfaces are kept
+#[cfg(target_os = "linux")]
+pub(crate) async fn prepare_netns(netns: &database::NetnsOption) -> Result<(), String> {
+    if !netns_path(&netns.name).exists() {
+        if !netns.create {
+            return Err(format!("netns {} does not exist", netns.name));
+        }
+        ip(&["netns", "add", &netns.name]).await?;
+        ip(&["-n", &netns.name, "link", "set", "lo", "up"]).await?;
+        log::info!("service: netns {} is created", netns.name);
+    }
+    let veth = match &netns.veth {
+        Some(veth) => veth,
+        None => return Ok(()),
+    };
+    if !std::path::Path::new("/sys/class/net")
+        .join(&veth.host_name)
+        .exists()
+    {
+        ip(&[
+            "link",
+            "add",
+            &veth.host_name,
+            "type",
+            "veth",
+            "peer",
+            "name",
+            &veth.peer_name,
+            "netns",
+            &netns.name,
+        ])
+        .await?;
+        log::info!(
+            "service: veth {} <-> {}@{} is created",
+            veth.host_name,
+            veth.peer_name,
+            netns.name
+        );
+    }
+    ip(&[
+        "addr",
+        "replace",
+        &veth.host_address,
+        "dev",
+        &veth.host_name,
+    ])
+    .await?;
+    ip(&["link", "set", &veth.host_name, "up"]).await?;
+    ip(&[
+        "-n",
+        &netns.name,
+        "addr",
+        "replace",
+        &veth.peer_address,
+        "dev",
+        &veth.peer_name,
+    ])
+    .await?;
+    ip(&["-n", &netns.name, "link", "set", &veth.peer_name, "up"]).await?;
+    if veth.default_route {
+        if let Some(host_ip) = veth.host_ip() {
+            ip(&[
+                "-n",
+                &netns.name,
+                "route",
+                "replace",
+                "default",
+                "via",
+                &host_ip.to_string(),
+            ])
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) async fn prepare_netns(_netns: &database::NetnsOption) -> Result<(), String> {
+    Err("network namespaces are only supported on linux".to_string())
+}
+
+// Move the core into the namespace before exec
+#[cfg(target_os = "linux")]
+pub(crate) fn apply_netns(cmd: &mut Command, netns: &database::NetnsOption) -> Result<(), String> {
+    use std::os::fd::AsRawFd;
+
+    let path = netns_path(&netns.name);
+    let file =
+        std::fs::File::open(&path).map_err(|e| format!("open {} failed: {}", path.display(), e))?;
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setns(file.as_raw_fd(), libc::CLONE_NEWNET) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn apply_netns(
+    _cmd: &mut Command,
+    _netns: &database::NetnsOption,
+) -> Result<(), String> {
+    Err("network namespaces are only supported on linux".to_string())
+}
+
+// A loopback or unspecified clash api listen is only reachable through the veth peer,
+// None if the listen can not be reached from the host
+pub(crate) fn netns_listen(
+    netns: &database::NetnsOption,
+    listen: SocketAddr,
+) -> Option<SocketAddr> {
+    let peer_ip = netns.veth.as_ref()?.peer_ip()?;
+    if listen.ip().is_loopback() || listen.ip().is_unspecified() {
+        Some(SocketAddr::new(peer_ip, listen.port()))
+    } else if listen.ip() == peer_ip {
+        Some(listen)
+    } else {
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn netns_path(name: &str) -> std::path::PathBuf {
+    std::path::Path::new("/run/netns").join(name)
+}
+
+#[cfg(target_os = "linux")]
+async fn ip(args: &[&str]) -> Result<(), String> {
+    let output = Command::new("ip")
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("run ip failed: {}", e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(format!(
+        "ip {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
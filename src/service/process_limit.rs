@@ -0,0 +1,172 @@
+use tokio::process::Command;
+
+use crate::database;
+
+// Apply privileges and resource limits to the core before exec
+#[cfg(target_os = "linux")]
+pub(crate) fn apply_process_limit(
+    cmd: &mut Command,
+    limit: &database::ProcessLimit,
+) -> Result<(), String> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt, path::PathBuf};
+
+    const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+    if limit.is_empty() {
+        return Ok(());
+    }
+    let caps = limit.capability_mask().map_err(|e| e.to_string())?;
+    // Prepared here, no allocation after fork
+    let cgroup_procs = match &limit.cgroup {
+        Some(cgroup) => {
+            let dir = PathBuf::from(CGROUP_ROOT).join(cgroup.trim_matches('/'));
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| format!("create cgroup {} failed: {}", dir.display(), e))?;
+            let procs = dir.join("cgroup.procs");
+            Some(CString::new(procs.as_os_str().as_bytes()).map_err(|e| e.to_string())?)
+        }
+        None => None,
+    };
+    let limit = limit.clone();
+    unsafe {
+        cmd.pre_exec(move || pre_exec(&limit, caps, cgroup_procs.as_deref()));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn apply_process_limit(
+    _cmd: &mut Command,
+    limit: &database::ProcessLimit,
+) -> Result<(), String> {
+    if limit.is_empty() {
+        return Ok(());
+    }
+    Err("process limits are only supported on linux".to_string())
+}
+
+// Runs in the forked child: privileged steps first, then switch user keeping the capabilities
+#[cfg(target_os = "linux")]
+fn pre_exec(
+    limit: &database::ProcessLimit,
+    caps: u64,
+    cgroup_procs: Option<&std::ffi::CStr>,
+) -> std::io::Result<()> {
+    use std::io::Error;
+
+    const PR_CAP_AMBIENT_RAISE: libc::c_ulong = 2;
+    const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+    #[repr(C)]
+    struct CapHeader {
+        version: u32,
+        pid: libc::c_int,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct CapData {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+
+    unsafe {
+        // Writing 0 moves the writing process
+        if let Some(procs) = cgroup_procs {
+            let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(Error::last_os_error());
+            }
+            let n = libc::write(fd, b"0".as_ptr().cast(), 1);
+            let err = Error::last_os_error();
+            libc::close(fd);
+            if n < 0 {
+                return Err(err);
+            }
+        }
+        if let Some(nice) = limit.nice {
+            if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                return Err(Error::last_os_error());
+            }
+        }
+        let rlimits = [
+            (libc::RLIMIT_NOFILE, limit.nofile),
+            (libc::RLIMIT_DATA, limit.memory),
+        ];
+        for (resource, value) in rlimits {
+            if let Some(value) = value {
+                let rlim = libc::rlimit {
+                    rlim_cur: value as libc::rlim_t,
+                    rlim_max: value as libc::rlim_t,
+                };
+                if libc::setrlimit(resource, &rlim) != 0 {
+                    return Err(Error::last_os_error());
+                }
+            }
+        }
+        if let Some(gid) = limit.gid {
+            if libc::setgroups(1, &gid) != 0 || libc::setgid(gid) != 0 {
+                return Err(Error::last_os_error());
+            }
+        }
+        if let Some(uid) = limit.uid {
+            if caps != 0 && libc::prctl(libc::PR_SET_KEEPCAPS, 1, 0, 0, 0) != 0 {
+                return Err(Error::last_os_error());
+            }
+            if libc::setuid(uid) != 0 {
+                return Err(Error::last_os_error());
+            }
+            if caps != 0 {
+                // Ambient capabilities must be both permitted and inheritable
+                let mut header = CapHeader {
+                    version: LINUX_CAPABILITY_VERSION_3,
+                    pid: 0,
+                };
+                let data = [
+                    CapData {
+                        effective: caps as u32,
+                        permitted: caps as u32,
+                        inheritable: caps as u32,
+                    },
+                    CapData {
+                        effective: (caps >> 32) as u32,
+                        permitted: (caps >> 32) as u32,
+                        inheritable: (caps >> 32) as u32,
+                    },
+                ];
+                if libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) != 0 {
+                    return Err(Error::last_os_error());
+                }
+                for cap in 0..64 {
+                    if caps & (1 << cap) != 0
+                        && libc::prctl(
+                            libc::PR_CAP_AMBIENT,
+                            PR_CAP_AMBIENT_RAISE,
+                            cap as libc::c_ulong,
+                            0,
+                            0,
+                        ) != 0
+                    {
+                        return Err(Error::last_os_error());
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Files the core needs after switching user
+pub(crate) fn chown_for_process_limit(
+    path: &std::path::Path,
+    limit: &database::ProcessLimit,
+) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if limit.uid.is_some() || limit.gid.is_some() {
+        std::os::unix::fs::chown(path, limit.uid, limit.gid)?;
+    }
+    #[cfg(not(unix))]
+    let _ = (path, limit);
+    Ok(())
+}
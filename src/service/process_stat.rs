@@ -0,0 +1,91 @@
+use std::time::Instant;
+
+// One sample of the core process read from /proc
+pub(crate) struct ProcessSample {
+    pub(crate) cpu_usage: f64, // %, of one cpu, 0 on the first sample
+    pub(crate) rss: u64,       // B
+    pub(crate) threads: u64,
+    pub(crate) fds: Option<u64>, // None if /proc/<pid>/fd is not readable
+    pub(crate) uptime: u64,      // s
+}
+
+// CPU usage is the delta between two samples, keep one sampler per process
+pub(crate) struct ProcessSampler {
+    pid: u32,
+    last: Option<(Instant, u64)>, // cpu time in ticks
+}
+
+impl ProcessSampler {
+    pub(crate) fn new(pid: u32) -> Self {
+        Self { pid, last: None }
+    }
+
+    // None if the process is gone or /proc is not available
+    #[cfg(target_os = "linux")]
+    pub(crate) fn sample(&mut self) -> Option<ProcessSample> {
+        let now = Instant::now();
+        let proc_dir = std::path::PathBuf::from(format!("/proc/{}", self.pid));
+        let stat = std::fs::read_to_string(proc_dir.join("stat")).ok()?;
+        // comm may contain spaces and parentheses, fields start after the last ')'
+        let fields = stat
+            .get(stat.rfind(')')? + 1..)?
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        // state is field 3, utime 14, stime 15, num_threads 20, starttime 22, rss 24
+        let field = |n: usize| fields.get(n - 3)?.parse::<u64>().ok();
+        let cpu_time = field(14)? + field(15)?;
+        let threads = field(20)?;
+        let start_time = field(22)?;
+        let rss = field(24)?;
+
+        let ticks = clock_ticks();
+        let cpu_usage = match self.last.replace((now, cpu_time)) {
+            Some((last_time, last_cpu_time)) => {
+                let elapsed = now.duration_since(last_time).as_secs_f64();
+                if elapsed > 0.0 {
+                    cpu_time.saturating_sub(last_cpu_time) as f64 / ticks as f64 / elapsed * 100.0
+                } else {
+                    0.0
+                }
+            }
+            None => 0.0,
+        };
+        let uptime = std::fs::read_to_string("/proc/uptime")
+            .ok()
+            .and_then(|s| s.split_whitespace().next()?.parse::<f64>().ok())
+            .map(|system_uptime| (system_uptime - start_time as f64 / ticks as f64).max(0.0) as u64)
+            .unwrap_or(0);
+        let fds = std::fs::read_dir(proc_dir.join("fd"))
+            .ok()
+            .map(|dir| dir.count() as u64);
+        Some(ProcessSample {
+            cpu_usage,
+            rss: rss * page_size(),
+            threads,
+            fds,
+            uptime,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) fn sample(&mut self) -> Option<ProcessSample> {
+        let _ = (self.pid, self.last);
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn clock_ticks() -> u64 {
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        n if n > 0 => n as u64,
+        _ => 100,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn page_size() -> u64 {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        n if n > 0 => n as u64,
+        _ => 4096,
+    }
+}
@@ -13,7 +13,13 @@ pub(crate) struct ScriptHandler {
 }
 
 impl ScriptHandler {
-    pub(crate) async fn new(manager: Arc<Manager>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+    pub(crate) async fn new(
+        manager: Arc<Manager>,
+        instance: &str,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if instance != database::DEFAULT_INSTANCE {
+            return Self::new_instance(manager, instance).await;
+        }
         let db = manager.get_database();
         let (db_1, db_2, db_3) = (db.clone(), db.clone(), db.clone());
         let (result_before_start, result_after_start, result_before_close, result_after_close) = tokio::join!(
@@ -47,6 +53,62 @@ impl ScriptHandler {
         })
     }
 
+    // Named instances refer to their scripts by id
+    async fn new_instance(
+        manager: Arc<Manager>,
+        instance: &str,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let db = manager.get_database();
+        let instance = database::get_instance(&db, instance.to_string())
+            .await
+            .map_err(|e| format!("get instance failed: {}", e))?;
+        let get_script = |id: Option<String>| {
+            let db = db.clone();
+            async move {
+                match id {
+                    Some(id) => match database::get_script(&db, id).await {
+                        Ok(v) => Ok(Some(v)),
+                        Err(database::Error::ScriptNotFound(id)) => {
+                            log::warn!("service: script not found, id: {}", id);
+                            Ok(None)
+                        }
+                        Err(e) => Err(e),
+                    },
+                    None => Ok(None),
+                }
+            }
+        };
+        let (result_before_start, result_after_start, result_before_close, result_after_close) = tokio::join!(
+            get_script(instance.before_start_script),
+            get_script(instance.after_start_script),
+            get_script(instance.before_close_script),
+            get_script(instance.after_close_script),
+        );
+        let before_start_script = match result_before_start {
+            Ok(v) => v,
+            Err(e) => return Err(format!("get before start script failed: {}", e).into()),
+        };
+        let after_start_script = match result_after_start {
+            Ok(v) => v,
+            Err(e) => return Err(format!("get after start script failed: {}", e).into()),
+        };
+        let before_close_script = match result_before_close {
+            Ok(v) => v,
+            Err(e) => return Err(format!("get before close script failed: {}", e).into()),
+        };
+        let after_close_script = match result_after_close {
+            Ok(v) => v,
+            Err(e) => return Err(format!("get after close script failed: {}", e).into()),
+        };
+        Ok(Self {
+            manager,
+            before_start_script,
+            after_start_script,
+            before_close_script,
+            after_close_script,
+        })
+    }
+
     fn set_extension(path: &mut PathBuf) {
         #[cfg(unix)]
         path.set_extension("sh");
@@ -61,64 +123,73 @@ impl ScriptHandler {
 
     async fn run_script(label: &str, manager: &Manager, script: &Option<database::Script>) {
         if let Some(script) = script {
-            log::debug!("service: run {}: tag: {}", label, script.tag);
-            let temp_script_file_name = common::random_uuid().replace("-", "");
-            let mut temp_script_file = manager.get_temp_dir_path().join(temp_script_file_name);
-            Self::set_extension(&mut temp_script_file);
+            let ok = Self::execute_script(label, manager, script).await;
+            manager.get_metrics().add_script_run(label, ok);
+        }
+    }
+
+    // Return false if the script can not be run or exits with failure
+    async fn execute_script(label: &str, manager: &Manager, script: &database::Script) -> bool {
+        log::debug!("service: run {}: tag: {}", label, script.tag);
+        let temp_script_file_name = common::random_uuid().replace("-", "");
+        let mut temp_script_file = manager.get_temp_dir_path().join(temp_script_file_name);
+        Self::set_extension(&mut temp_script_file);
+        {
+            std::fs::remove_file(&temp_script_file).ok();
+            let mut f = match fs::File::options()
+                .create(true)
+                .write(true)
+                .open(&temp_script_file)
+                .await
             {
-                std::fs::remove_file(&temp_script_file).ok();
-                let mut f = match fs::File::options()
-                    .create(true)
-                    .write(true)
-                    .open(&temp_script_file)
-                    .await
-                {
-                    Ok(v) => v,
-                    Err(e) => {
-                        log::error!("service: create {} failed: {}", label, e);
-                        return;
-                    }
-                };
-                if let Err(e) = f.write_all(script.content.as_bytes()).await {
-                    log::error!("service: write {} failed: {}", label, e);
-                    return;
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("service: create {} failed: {}", label, e);
+                    return false;
                 }
+            };
+            if let Err(e) = f.write_all(script.content.as_bytes()).await {
+                log::error!("service: write {} failed: {}", label, e);
+                return false;
             }
+        }
 
-            // Set Permission
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
+        // Set Permission
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
 
-                let permission = std::fs::Permissions::from_mode(0o755);
-                if let Err(e) = std::fs::set_permissions(&temp_script_file, permission) {
-                    log::error!("service: {}: set permission failed: {}", label, e);
-                    return;
-                }
+            let permission = std::fs::Permissions::from_mode(0o755);
+            if let Err(e) = std::fs::set_permissions(&temp_script_file, permission) {
+                log::error!("service: {}: set permission failed: {}", label, e);
+                return false;
             }
+        }
 
-            match Command::new(&temp_script_file).output().await {
-                Ok(output) => {
-                    let stdout = Self::bytes_to_string(output.stdout);
-                    let stderr = Self::bytes_to_string(output.stderr);
-                    let mut s = format!("exit code: {}", output.status);
-                    if !stdout.is_empty() {
-                        s.push_str(&format!("; stdout: {}", stdout));
-                    }
-                    if !stderr.is_empty() {
-                        s.push_str(&format!("; stderr: {}", stderr));
-                    }
-                    log::debug!("service: run {} output: {}", label, s);
+        let ok = match Command::new(&temp_script_file).output().await {
+            Ok(output) => {
+                let stdout = Self::bytes_to_string(output.stdout);
+                let stderr = Self::bytes_to_string(output.stderr);
+                let mut s = format!("exit code: {}", output.status);
+                if !stdout.is_empty() {
+                    s.push_str(&format!("; stdout: {}", stdout));
                 }
-                Err(e) => {
-                    log::error!("service: run {} failed: {}", label, e);
+                if !stderr.is_empty() {
+                    s.push_str(&format!("; stderr: {}", stderr));
                 }
+                log::debug!("service: run {} output: {}", label, s);
+                output.status.success()
+            }
+            Err(e) => {
+                log::error!("service: run {} failed: {}", label, e);
+                false
             }
+        };
 
-            std::fs::remove_file(&temp_script_file).unwrap_or_else(|e| {
-                log::error!("service: remove {} failed: {}", label, e);
-            });
-        }
+        std::fs::remove_file(&temp_script_file).unwrap_or_else(|e| {
+            log::error!("service: remove {} failed: {}", label, e);
+        });
+        ok
     }
 
     pub(crate) async fn run_before_start_script(&self) {
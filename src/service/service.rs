@@ -1,96 +1,337 @@
 use std::{
     error::Error,
+    fmt,
     net::SocketAddr,
     path::PathBuf,
-    process::Stdio,
+    process::{ExitStatus, Stdio},
     str::FromStr,
     sync::{
         atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
         Arc, RwLock,
     },
+    time::{Duration, Instant},
 };
 
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader},
     process::{Child, Command},
-    sync::{mpsc, Mutex, Notify},
+    sync::{mpsc, oneshot, watch, Mutex, MutexGuard, Notify},
 };
 use tokio_util::sync::CancellationToken;
 
 use crate::{common, database, manager::Manager};
 
+const PROCESS_SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+const STAT_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ServiceState {
+    Stopped,
+    Starting,
+    Running,
+    Reloading,
+    Stopping,
+    Restarting, // waiting for the restart backoff
+    Crashed,
+}
+
+impl ServiceState {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Stopped => "stopped",
+            Self::Starting => "starting",
+            Self::Running => "running",
+            Self::Reloading => "reloading",
+            Self::Stopping => "stopping",
+            Self::Restarting => "restarting",
+            Self::Crashed => "crashed",
+        }
+    }
+}
+
+impl fmt::Display for ServiceState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 pub(crate) struct Status {
+    pub(crate) state: RwLock<ServiceState>,
+    pub(crate) state_time: AtomicU64, // unix timestamp (s)
+    pub(crate) last_exit_reason: RwLock<String>,
+    pub(crate) last_exit_time: AtomicU64, // unix timestamp (s)
     pub(crate) is_running: AtomicBool,
     pub(crate) running_config: RwLock<String>,
     pub(crate) core_version: RwLock<String>,
     pub(crate) memory_usage: AtomicU64,       // B
     pub(crate) connection_count: AtomicUsize, // count
-    pub(crate) upload_traffic: AtomicU64,     // B
-    pub(crate) download_traffic: AtomicU64,   // B
-    pub(crate) upload_speed: AtomicU64,       // B/s
-    pub(crate) download_speed: AtomicU64,     // B/s
+    pub(crate) connections: RwLock<Arc<Vec<super::ClashAPIConnection>>>,
+    pub(crate) upload_traffic: AtomicU64,   // B
+    pub(crate) download_traffic: AtomicU64, // B
+    pub(crate) upload_speed: AtomicU64,     // B/s
+    pub(crate) download_speed: AtomicU64,   // B/s
+    pub(crate) traffic_live: AtomicBool,    // clash api streams are connected
+    pub(crate) speed_live: AtomicBool,
+    pub(crate) memory_live: AtomicBool,
+    pub(crate) process_cpu_usage: AtomicU64, // 0.01 %, sampled from /proc
+    pub(crate) process_memory: AtomicU64,    // B, RSS
+    pub(crate) process_threads: AtomicU64,   // count
+    pub(crate) process_fds: AtomicU64,       // count
+    pub(crate) process_uptime: AtomicU64,    // s
+    pub(crate) restart_count: AtomicU64,     // count
+    pub(crate) restart_total: AtomicU64,     // count, since the manager started
+    pub(crate) is_restarting: AtomicBool,
+    pub(crate) last_failure: RwLock<String>,
+    pub(crate) confirm_config: RwLock<String>,
+    pub(crate) confirm_deadline: AtomicU64, // unix timestamp (s)
 }
 
 impl Status {
     pub(crate) fn clean_data(&self) {
         self.memory_usage.store(0, Ordering::Relaxed);
         self.connection_count.store(0, Ordering::Relaxed);
+        *self.connections.write().unwrap() = Arc::new(Vec::new());
         self.upload_traffic.store(0, Ordering::Relaxed);
         self.download_traffic.store(0, Ordering::Relaxed);
         self.upload_speed.store(0, Ordering::Relaxed);
         self.download_speed.store(0, Ordering::Relaxed);
+        self.traffic_live.store(false, Ordering::Relaxed);
+        self.speed_live.store(false, Ordering::Relaxed);
+        self.memory_live.store(false, Ordering::Relaxed);
+        self.process_cpu_usage.store(0, Ordering::Relaxed);
+        self.process_memory.store(0, Ordering::Relaxed);
+        self.process_threads.store(0, Ordering::Relaxed);
+        self.process_fds.store(0, Ordering::Relaxed);
+        self.process_uptime.store(0, Ordering::Relaxed);
+    }
+
+    // Traffic, speed and memory are all streamed from the clash api
+    pub(crate) fn is_stats_live(&self) -> bool {
+        self.traffic_live.load(Ordering::Relaxed)
+            && self.speed_live.load(Ordering::Relaxed)
+            && self.memory_live.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn get_state(&self) -> ServiceState {
+        *self.state.read().unwrap()
+    }
+
+    // Return false if the state is unchanged
+    fn set_state(&self, state: ServiceState) -> bool {
+        let mut current = self.state.write().unwrap();
+        if *current == state {
+            return false;
+        }
+        *current = state;
+        self.state_time
+            .store(chrono::Utc::now().timestamp() as u64, Ordering::Relaxed);
+        true
     }
 }
 
 impl Default for Status {
     fn default() -> Self {
         Self {
+            state: RwLock::new(ServiceState::Stopped),
+            state_time: AtomicU64::new(0),
+            last_exit_reason: RwLock::new(String::new()),
+            last_exit_time: AtomicU64::new(0),
             is_running: AtomicBool::new(false),
             running_config: RwLock::new(String::new()),
             core_version: RwLock::new(String::new()),
             memory_usage: AtomicU64::new(0),
             connection_count: AtomicUsize::new(0),
+            connections: RwLock::new(Arc::new(Vec::new())),
             upload_traffic: AtomicU64::new(0),
             download_traffic: AtomicU64::new(0),
             upload_speed: AtomicU64::new(0),
             download_speed: AtomicU64::new(0),
+            traffic_live: AtomicBool::new(false),
+            speed_live: AtomicBool::new(false),
+            memory_live: AtomicBool::new(false),
+            process_cpu_usage: AtomicU64::new(0),
+            process_memory: AtomicU64::new(0),
+            process_threads: AtomicU64::new(0),
+            process_fds: AtomicU64::new(0),
+            process_uptime: AtomicU64::new(0),
+            restart_count: AtomicU64::new(0),
+            restart_total: AtomicU64::new(0),
+            is_restarting: AtomicBool::new(false),
+            last_failure: RwLock::new(String::new()),
+            confirm_config: RwLock::new(String::new()),
+            confirm_deadline: AtomicU64::new(0),
+        }
+    }
+}
+
+// Run history record of one core process
+struct RunRecord {
+    db: database::Database,
+    id: Option<String>, // None if the record can not be added
+    stop_reason: RwLock<Option<database::RunReason>>,
+}
+
+impl RunRecord {
+    async fn new(db: database::Database, run: database::ServiceRun) -> Self {
+        let id = match database::add_service_run(&db, run).await {
+            Ok(run) => Some(run.id),
+            Err(e) => {
+                log::error!("service: add service run failed: {}", e);
+                None
+            }
+        };
+        Self {
+            db,
+            id,
+            stop_reason: RwLock::new(None),
+        }
+    }
+
+    async fn set_ready(&self) {
+        if let Some(id) = &self.id {
+            let now = chrono::Utc::now().timestamp_millis();
+            if let Err(e) = database::set_service_run_ready(&self.db, id.clone(), now).await {
+                log::error!("service: update service run failed: {}", e);
+            }
+        }
+    }
+
+    // Keep the first reason, the core may be stopped again after it has exited
+    fn set_stop_reason(&self, reason: database::RunReason) {
+        let mut stop_reason = self.stop_reason.write().unwrap();
+        if stop_reason.is_none() {
+            *stop_reason = Some(reason);
+        }
+    }
+
+    async fn finish(&self, status: Option<ExitStatus>) {
+        let id = match &self.id {
+            Some(id) => id.clone(),
+            None => return,
+        };
+        let reason = self.stop_reason.read().unwrap().unwrap_or_else(|| {
+            match status.map(|s| s.success()).unwrap_or(false) {
+                true => database::RunReason::Exit,
+                false => database::RunReason::Crash,
+            }
+        });
+        let exit_code = status.and_then(|s| s.code());
+        #[cfg(unix)]
+        let exit_signal = status.and_then(|s| std::os::unix::process::ExitStatusExt::signal(&s));
+        #[cfg(not(unix))]
+        let exit_signal = None;
+        let now = chrono::Utc::now().timestamp_millis();
+        if let Err(e) =
+            database::finish_service_run(&self.db, id, now, reason, exit_code, exit_signal).await
+        {
+            log::error!("service: update service run failed: {}", e);
         }
     }
 }
 
+enum ServiceExit {
+    Cancelled,
+    Exited {
+        status: Option<ExitStatus>,
+        uptime: Duration,
+    },
+}
+
 struct ServiceInner {
+    id: u64,
     token: CancellationToken,
     receiver: mpsc::Receiver<()>,
+    ready: watch::Receiver<bool>,
+    exit_receiver: Option<oneshot::Receiver<ServiceExit>>,
     config: database::Config,
+    source: database::Config, // config before rewritten
+    pid: Option<u32>,
+    core_path: String,
+    config_path: PathBuf,
+    options: ProcessOptions,
+    listen: SocketAddr,
+    secret: Option<String>,
+    clash_api: super::ClashAPIClient, // shared by the api handlers, keeps its connections
+    log_started: watch::Receiver<u64>,
+    stat_config_id: Arc<RwLock<String>>, // the config the traffic is added to, changed by reload
+    stat_flush: mpsc::Sender<oneshot::Sender<()>>,
+    run: Arc<RunRecord>,
     status: Arc<super::State<Status>>,
 }
 
+// Clash api of the current core, read by the api handlers without the lifecycle lock
+#[derive(Clone)]
+struct ClashAPISnapshot {
+    client: super::ClashAPIClient,
+    config_id: String, // the running config, changed by reload
+    ready: watch::Receiver<bool>,
+}
+
+// Everything applied to the core process besides the config, changes need a restart
+#[derive(Clone, PartialEq)]
+struct ProcessOptions {
+    launch: database::LaunchOption, // working dir resolved
+    limit: database::ProcessLimit,
+    netns: Option<database::NetnsOption>,
+}
+
+// Config rewritten for the core, checked before the running instance is stopped
+struct PreparedConfig {
+    core_path: String,
+    config: database::Config,
+    source: database::Config,
+    options: ProcessOptions,
+    listen: SocketAddr,
+    secret: Option<String>,
+    core_version: String,
+}
+
 impl ServiceInner {
-    async fn new(
-        manager: Arc<Manager>,
+    // Default Secret: used when the config sets no clash api secret, random if None
+    async fn prepare(
         core_path: String,
         mut config: database::Config,
-        log_queue: Arc<super::LogQueue<String>>,
-        status: Arc<super::State<Status>>,
-    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
-        *status.running_config.write().unwrap() = config.tag.clone();
-        status.notify();
-        let script_handler = super::ScriptHandler::new(manager).await.map_err(|err| {
-            log::error!("service: script handler init failed: {}", &err);
-            Into::<Box<dyn Error + Send + Sync>>::into(format!(
-                "service: script handler init failed: {}",
-                err
-            ))
-        })?;
+        options: ProcessOptions,
+        default_secret: Option<String>,
+    ) -> Result<PreparedConfig, Box<dyn Error + Send + Sync>> {
+        let source = config.clone();
         // Check Config
-        let (listen, secret) = Self::check_config(&mut config.config)?;
-        let listen = SocketAddr::from_str(&listen).map_err(|err| {
+        let (listen, secret) = Self::check_config(&mut config.config, default_secret)?;
+        let mut listen = SocketAddr::from_str(&listen).map_err(|err| {
             log::error!("service: clash api: invalid listen address: {}", &err);
             Into::<Box<dyn Error + Send + Sync>>::into(format!(
                 "service: invalid listen address: {}",
                 err
             ))
         })?;
+        if let Some(netns) = &options.netns {
+            match super::netns_listen(netns, listen) {
+                Some(netns_listen) => {
+                    log::debug!(
+                        "service: clash api listen {} is moved to {} in netns {}",
+                        listen,
+                        netns_listen,
+                        netns.name
+                    );
+                    Self::set_clash_api_listen(&mut config.config, netns_listen);
+                    listen = netns_listen;
+                }
+                // The clash api handlers and the readiness probe must reach the core
+                None => {
+                    log::error!(
+                        "service: clash api listen {} is not reachable in netns {}",
+                        listen,
+                        netns.name
+                    );
+                    return Err(format!(
+                        "service: clash api listen {} is not reachable in netns {}, use a loopback or the veth peer address with a veth",
+                        listen, netns.name
+                    )
+                    .into());
+                }
+            }
+        }
 
         // Set Permission
         #[cfg(unix)]
@@ -115,12 +356,12 @@ impl ServiceInner {
                     err
                 ))
             })?;
+        let mut core_version = String::new();
         for line in version_output.split('\n').collect::<Vec<&str>>() {
             let line = line.trim();
             if line.starts_with("sing-box version") {
                 let version = line.trim_start_matches("sing-box version").trim();
-                *status.core_version.write().unwrap() = version.to_owned();
-                status.notify();
+                core_version = version.to_owned();
                 log::debug!("service: core version: {}", version);
             } else if line.starts_with("Tags:") {
                 let tag_str = line.trim_start_matches("Tags:").trim();
@@ -131,10 +372,26 @@ impl ServiceInner {
                 log::debug!("service: core tags: {:?}", tags);
             }
         }
-        //
-        let config_content = config.config.to_string();
-        let mut cmd = Command::new(core_path);
-        cmd.args(["run", "--config", "stdin", "--disable-color"]);
+        Ok(PreparedConfig {
+            core_path,
+            config,
+            source,
+            options,
+            listen,
+            secret,
+            core_version,
+        })
+    }
+
+    // Run `core check` against the rewritten config, return the core output on failure
+    async fn check_core_config(prepared: &PreparedConfig) -> Result<(), String> {
+        const CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+        let config_content = prepared.config.config.to_string();
+        let mut cmd = Command::new(Self::absolute_path(&prepared.core_path));
+        cmd.args(["check", "--config", "stdin", "--disable-color"]);
+        Self::apply_launch_option(&mut cmd, &prepared.options.launch)
+            .map_err(|e| format!("create working dir failed: {}", e))?;
         cmd.kill_on_drop(true);
         cmd.stdin(Stdio::piped());
         cmd.stdout(Stdio::piped());
@@ -143,72 +400,395 @@ impl ServiceInner {
         #[cfg(windows)]
         cmd.creation_flags(0x0800_0000); // CREATE_NO_WINDOW
 
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| format!("run core check failed: {}", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(config_content.as_bytes())
+                .await
+                .map_err(|e| format!("write config to stdin failed: {}", e))?;
+        }
+        let output = tokio::time::timeout(CHECK_TIMEOUT, child.wait_with_output())
+            .await
+            .map_err(|_| format!("core check timed out after {}s", CHECK_TIMEOUT.as_secs()))?
+            .map_err(|e| format!("run core check failed: {}", e))?;
+        if output.status.success() {
+            return Ok(());
+        }
+        let mut s = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !stdout.trim().is_empty() {
+            if !s.is_empty() {
+                s.push('\n');
+            }
+            s.push_str(stdout.trim());
+        }
+        if s.is_empty() {
+            s = output.status.to_string();
+        }
+        Err(s)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn new(
+        manager: Arc<Manager>,
+        name: &str,
+        id: u64,
+        prepared: PreparedConfig,
+        config_path: PathBuf,
+        reason: database::RunReason,
+        log_queue: Arc<super::LogQueue<String>>,
+        status: Arc<super::State<Status>>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let PreparedConfig {
+            core_path,
+            config,
+            source,
+            options,
+            listen,
+            secret,
+            core_version,
+        } = prepared;
+        *status.running_config.write().unwrap() = config.tag.clone();
+        *status.core_version.write().unwrap() = core_version.clone();
+        status.notify();
+        let db = manager.get_database();
+        let ready_timeout = database::get_ready_timeout(&manager.get_database())
+            .await
+            .map_err(|err| {
+                log::error!("service: get ready timeout failed: {}", &err);
+                Into::<Box<dyn Error + Send + Sync>>::into(format!(
+                    "service: get ready timeout failed: {}",
+                    err
+                ))
+            })?;
+        // Restored once the clash api is ready, a missing selection is not fatal
+        let selections = database::list_proxy_selection(&db, source.id.clone())
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("service: list proxy selection failed: {}", e);
+                Vec::new()
+            });
+        let mode = database::get_clash_mode(&db, source.id.clone())
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("service: get clash mode failed: {}", e);
+                None
+            });
+        let script_handler = super::ScriptHandler::new(manager, name)
+            .await
+            .map_err(|err| {
+                log::error!("service: script handler init failed: {}", &err);
+                Into::<Box<dyn Error + Send + Sync>>::into(format!(
+                    "service: script handler init failed: {}",
+                    err
+                ))
+            })?;
+        // The core reads the config from file, so that it can be reloaded by SIGHUP
+        Self::write_config_file(&config_path, &config.config).await?;
+        let mut cmd = Command::new(Self::absolute_path(&core_path));
+        cmd.arg("run");
+        cmd.arg("--config");
+        cmd.arg(&config_path);
+        cmd.arg("--disable-color");
+        cmd.args(&options.launch.args);
+        Self::apply_launch_option(&mut cmd, &options.launch).map_err(|e| {
+            log::error!("service: create working dir failed: {}", &e);
+            Into::<Box<dyn Error + Send + Sync>>::into(format!(
+                "service: create working dir failed: {}",
+                e
+            ))
+        })?;
+        // Join the netns while still privileged
+        if let Some(netns) = &options.netns {
+            super::prepare_netns(netns)
+                .await
+                .and_then(|_| super::apply_netns(&mut cmd, netns))
+                .map_err(|e| {
+                    log::error!("service: prepare netns failed: {}", &e);
+                    Into::<Box<dyn Error + Send + Sync>>::into(format!(
+                        "service: prepare netns failed: {}",
+                        e
+                    ))
+                })?;
+        }
+        super::apply_process_limit(&mut cmd, &options.limit).map_err(|e| {
+            log::error!("service: apply process limit failed: {}", &e);
+            Into::<Box<dyn Error + Send + Sync>>::into(format!(
+                "service: apply process limit failed: {}",
+                e
+            ))
+        })?;
+        let working_dir = options.launch.working_dir.iter().map(PathBuf::from);
+        for path in working_dir.chain([config_path.clone()]) {
+            super::chown_for_process_limit(&path, &options.limit).map_err(|e| {
+                log::error!("service: chown {} failed: {}", path.display(), &e);
+                Into::<Box<dyn Error + Send + Sync>>::into(format!(
+                    "service: chown {} failed: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+        }
+        cmd.kill_on_drop(true);
+        cmd.stdin(Stdio::null());
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+
+        #[cfg(windows)]
+        cmd.creation_flags(0x0800_0000); // CREATE_NO_WINDOW
+
         script_handler.run_before_start_script().await;
 
-        let mut child = cmd.spawn().map_err(|e| {
+        let child = cmd.spawn().map_err(|e| {
             log::error!("service: start service failed: {}", &e);
             Into::<Box<dyn Error + Send + Sync>>::into(format!(
                 "service: start service failed: {}",
                 e
             ))
         })?;
-        match &mut child.stdin {
-            Some(stdin) => {
-                stdin
-                    .write_all(config_content.as_bytes())
-                    .await
-                    .map_err(|e| {
-                        log::error!("service: write config to stdin failed: {}", &e);
-                        format!("service: write config to stdin failed: {}", e)
-                    })?;
-            }
-            None => {
-                log::error!("service: stdin is not piped");
-                return Err("service: stdin is not piped".into());
-            }
-        }
+        let pid = child.id();
+        let run = Arc::new(
+            RunRecord::new(
+                db.clone(),
+                database::ServiceRun {
+                    id: String::new(),
+                    instance: name.to_string(),
+                    config_id: config.id.clone(),
+                    config_tag: config.tag.clone(),
+                    core_path: core_path.clone(),
+                    core_version,
+                    pid,
+                    start_reason: reason.as_str().to_string(),
+                    stop_reason: None,
+                    start_time: chrono::Utc::now().timestamp_millis(),
+                    ready_time: None,
+                    stop_time: None,
+                    exit_code: None,
+                    exit_signal: None,
+                },
+            )
+            .await,
+        );
+        let script_handler = Arc::new(script_handler);
         let (sender, receiver) = mpsc::channel(1);
-        let started_notify_handle = Arc::new(Notify::new());
+        let (exit_sender, exit_receiver) = oneshot::channel();
+        let (log_started_sender, log_started_receiver) = watch::channel(0);
+        let (ready_sender, ready_receiver) = watch::channel(false);
+        let ready = ready_receiver.clone();
         let token = CancellationToken::new();
-        let token_handle = token.clone();
-        let status_handle = status.clone();
-        let status_clash_api_handle = status.clone();
-        let token_clash_api_handle = token.clone();
-        let sender_clash_api_handle = sender.clone();
-        let started_notify_clash_api_handle = started_notify_handle.clone();
+        let clash_api = super::ClashAPIClient::new(listen, secret.clone());
+        let client_ready_handle = clash_api.clone();
+        let log_started_ready_handle = log_started_receiver.clone();
+        let (listen_inner, secret_inner) = (listen, secret.clone());
+        let (
+            token_ready_handle,
+            script_handler_ready_handle,
+            run_ready_handle,
+            sender_ready_handle,
+        ) = (
+            token.clone(),
+            script_handler.clone(),
+            run.clone(),
+            sender.clone(),
+        );
+        tokio::spawn(async move {
+            Self::ready_handle(
+                client_ready_handle,
+                Duration::from_secs(ready_timeout),
+                mode,
+                selections,
+                log_started_ready_handle,
+                ready_sender,
+                script_handler_ready_handle,
+                run_ready_handle,
+                token_ready_handle,
+                sender_ready_handle,
+            )
+            .await
+        });
+        let (status_clash_api_handle, token_clash_api_handle, sender_clash_api_handle) =
+            (status.clone(), token.clone(), sender.clone());
         tokio::spawn(async move {
             Self::clash_api_handle(
                 listen,
                 secret,
-                started_notify_clash_api_handle,
+                ready_receiver,
                 status_clash_api_handle,
                 token_clash_api_handle,
                 sender_clash_api_handle,
             )
             .await
         });
+        if let Some(pid) = pid {
+            let (status_process_handle, token_process_handle, sender_process_handle) =
+                (status.clone(), token.clone(), sender.clone());
+            tokio::spawn(async move {
+                Self::process_handle(
+                    pid,
+                    status_process_handle,
+                    token_process_handle,
+                    sender_process_handle,
+                )
+                .await
+            });
+        }
+        let stat_config_id = Arc::new(RwLock::new(config.id.clone()));
+        let (stat_flush, stat_flush_receiver) = mpsc::channel(1);
+        let (db_stat_handle, name_stat_handle, config_id_stat_handle) =
+            (db.clone(), name.to_string(), stat_config_id.clone());
+        let (status_stat_handle, token_stat_handle, sender_stat_handle) =
+            (status.clone(), token.clone(), sender.clone());
+        tokio::spawn(async move {
+            Self::stat_handle(
+                db_stat_handle,
+                name_stat_handle,
+                config_id_stat_handle,
+                stat_flush_receiver,
+                status_stat_handle,
+                token_stat_handle,
+                sender_stat_handle,
+            )
+            .await
+        });
+        let (token_handle, run_handle, status_handle) =
+            (token.clone(), run.clone(), status.clone());
         tokio::spawn(async move {
             Self::child_handle(
                 token_handle,
                 sender,
+                exit_sender,
                 child,
                 script_handler,
                 log_queue,
-                started_notify_handle,
+                log_started_sender,
+                run_handle,
                 status_handle,
             )
             .await
         });
         Ok(Self {
+            id,
             token,
             receiver,
+            ready,
+            exit_receiver: Some(exit_receiver),
             config,
+            source,
+            pid,
+            core_path,
+            config_path,
+            options,
+            listen: listen_inner,
+            secret: secret_inner,
+            clash_api,
+            log_started: log_started_receiver,
+            stat_config_id,
+            stat_flush,
+            run,
             status,
         })
     }
 
+    // The working dir changes the base of relative paths
+    fn absolute_path(path: &str) -> PathBuf {
+        let p = PathBuf::from(path);
+        if p.components().count() > 1 {
+            std::path::absolute(&p).unwrap_or(p)
+        } else {
+            p
+        }
+    }
+
+    // Env and working dir, shared by `check` and `run`
+    fn apply_launch_option(
+        cmd: &mut Command,
+        launch: &database::LaunchOption,
+    ) -> std::io::Result<()> {
+        cmd.envs(&launch.env);
+        if let Some(dir) = &launch.working_dir {
+            std::fs::create_dir_all(dir)?;
+            cmd.current_dir(dir);
+        }
+        Ok(())
+    }
+
+    async fn write_config_file(
+        path: &PathBuf,
+        config: &serde_json::Value,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let f = |e: std::io::Error| {
+            log::error!("service: write config file failed: {}", &e);
+            Into::<Box<dyn Error + Send + Sync>>::into(format!(
+                "service: write config file failed: {}",
+                e
+            ))
+        };
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // The config holds the clash api secret, never readable by others
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(path).await.map_err(f)?;
+        // The mode only applies to a new file, tighten one left by an older version
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))
+                .await
+                .map_err(f)?;
+        }
+        file.write_all(config.to_string().as_bytes())
+            .await
+            .map_err(f)?;
+        file.flush().await.map_err(f)
+    }
+
+    // Wait for the stat handle to write the samples so far, it is gone if the core stopped
+    async fn flush_stat(&self) {
+        let (done, done_receiver) = oneshot::channel();
+        if self.stat_flush.send(done).await.is_ok() {
+            let _ = done_receiver.await;
+        }
+    }
+
+    // Ask the core to reload the config file, return false if it can not be signaled
+    fn reload(&self) -> bool {
+        cfg_if::cfg_if! {
+            if #[cfg(unix)] {
+                use nix::unistd::Pid;
+                use nix::sys::signal::{self, Signal};
+
+                let pid = match self.pid {
+                    Some(pid) => pid,
+                    None => return false,
+                };
+                if let Err(e) = signal::kill(Pid::from_raw(pid as i32), Signal::SIGHUP) {
+                    log::error!("service: send SIGHUP failed: {}", e);
+                    return false;
+                }
+                true
+            } else {
+                false
+            }
+        }
+    }
+
+    // Only called after check_config, which makes sure the clash api object exists
+    fn set_clash_api_listen(config: &mut serde_json::Value, listen: SocketAddr) {
+        if let Some(serde_json::Value::Object(clash_api)) = config
+            .get_mut("experimental")
+            .and_then(|experimental| experimental.get_mut("clash_api"))
+        {
+            clash_api.insert("external_controller".into(), listen.to_string().into());
+        }
+    }
+
     fn check_config(
         config: &mut serde_json::Value,
+        default_secret: Option<String>,
     ) -> Result<(String, Option<String>), Box<dyn Error + Send + Sync>> {
         let map = match config {
             serde_json::Value::Object(m) => m,
@@ -252,7 +832,9 @@ impl ServiceInner {
                     if let Some(serde_json::Value::String(secret)) = clash_api_map.get("secret") {
                         Some(secret.clone())
                     } else if init_secret {
-                        let secret = common::random_uuid().replace("-", "");
+                        let secret = default_secret
+                            .clone()
+                            .unwrap_or_else(|| common::random_uuid().replace("-", ""));
                         clash_api_map.insert("secret".into(), secret.clone().into());
                         Some(secret)
                     } else {
@@ -283,24 +865,31 @@ impl ServiceInner {
         }
     }
 
-    async fn cancel_and_wait(&mut self) {
+    async fn cancel_and_wait(&mut self, reason: database::RunReason) {
+        self.run.set_stop_reason(reason);
         self.token.cancel();
         let _ = self.receiver.recv().await;
         self.status.clean_data();
         self.status.notify();
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn child_handle(
         token: CancellationToken,
         _sender: mpsc::Sender<()>,
+        exit_sender: oneshot::Sender<ServiceExit>,
         mut child: Child,
-        script_handler: super::ScriptHandler,
+        script_handler: Arc<super::ScriptHandler>,
         log_queue: Arc<super::LogQueue<String>>,
-        started_notify: Arc<Notify>,
+        log_started: watch::Sender<u64>, // count, the core logs it again when reloaded
+        run: Arc<RunRecord>,
         status: Arc<super::State<Status>>,
     ) {
         status.is_running.store(true, Ordering::Relaxed);
         status.notify();
+        let started_at = Instant::now();
+        let mut exit = ServiceExit::Cancelled;
+        let exit_status;
         let mut stdout_buf_reader = BufReader::new(child.stdout.take().unwrap());
         let mut stderr_buf_reader = BufReader::new(child.stderr.take().unwrap());
         let mut stdout_string = String::new();
@@ -315,13 +904,10 @@ impl ServiceInner {
                     if let Ok(_) = res {
                         if stdout_string.len() > 0 {
                             log::debug!("service: stdout: {}", stdout_string.trim_end());
-                            let is_started_msg = stdout_string.contains("sing-box started");
-                            log_queue.push_data(format!("[{}] stdout: {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), stdout_string.trim_end()));
-                            if is_started_msg {
-                                script_handler.run_after_start_script().await;
-                                log::debug!("service: core is started");
-                                started_notify.notify_waiters();
+                            if stdout_string.contains("sing-box started") {
+                                log_started.send_modify(|n| *n += 1);
                             }
+                            log_queue.push_data(format!("[{}] stdout: {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), stdout_string.trim_end()));
                         }
                     }
                     stdout_string.clear();
@@ -330,42 +916,97 @@ impl ServiceInner {
                     if let Ok(_) = res {
                         if stderr_string.len() > 0 {
                             log::debug!("service: stderr: {}", stderr_string.trim_end());
-                            let is_started_msg = stderr_string.contains("sing-box started");
-                            log_queue.push_data(format!("[{}] stderr: {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), stderr_string.trim_end()));
-                            if is_started_msg {
-                                script_handler.run_after_start_script().await;
-                                log::debug!("service: core is started");
-                                started_notify.notify_waiters();
+                            if stderr_string.contains("sing-box started") {
+                                log_started.send_modify(|n| *n += 1);
                             }
+                            log_queue.push_data(format!("[{}] stderr: {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), stderr_string.trim_end()));
                         }
                     }
                     stderr_string.clear();
                 }
                 res = child.wait() => {
-                    if let Err(e) = res {
-                        log::error!("service: service exited with error: {}", e);
-                    }
+                    exit_status = match res {
+                        Ok(s) => {
+                            log::warn!("service: service exited: {}", s);
+                            Some(s)
+                        }
+                        Err(e) => {
+                            log::error!("service: service exited with error: {}", e);
+                            None
+                        }
+                    };
+                    exit = ServiceExit::Exited {
+                        status: exit_status,
+                        uptime: started_at.elapsed(),
+                    };
                     break;
                 }
                 _ = token.cancelled() => {
                     script_handler.run_before_close_script().await;
                     log::debug!("service: service is cancelled");
-                    Self::stop_process(child).await;
+                    exit_status = Self::stop_process(child).await;
                     break;
                 }
             }
         }
+        if let ServiceExit::Exited { .. } = exit {
+            // The last lines usually explain why the core exited
+            const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+            let _ = tokio::time::timeout(DRAIN_TIMEOUT, async {
+                tokio::join!(
+                    Self::drain_output(
+                        "stdout",
+                        &mut stdout_buf_reader,
+                        &mut stdout_string,
+                        &log_queue
+                    ),
+                    Self::drain_output(
+                        "stderr",
+                        &mut stderr_buf_reader,
+                        &mut stderr_string,
+                        &log_queue
+                    ),
+                )
+            })
+            .await;
+        }
         script_handler.run_after_close_script().await;
         log_queue.push_data(format!(
             "[{}] service is closed",
             chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
         ));
         token.cancel();
+        run.finish(exit_status).await;
         status.is_running.store(false, Ordering::Relaxed);
         status.notify();
+        let _ = exit_sender.send(exit);
+    }
+
+    async fn drain_output<R: AsyncBufRead + Unpin>(
+        label: &str,
+        reader: &mut R,
+        buf: &mut String,
+        log_queue: &super::LogQueue<String>,
+    ) {
+        loop {
+            match reader.read_line(buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    log::debug!("service: {}: {}", label, buf.trim_end());
+                    log_queue.push_data(format!(
+                        "[{}] {}: {}",
+                        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        label,
+                        buf.trim_end()
+                    ));
+                    buf.clear();
+                }
+            }
+        }
     }
 
-    async fn stop_process(mut child: Child) {
+    // Return the exit status if the core has been reaped
+    async fn stop_process(mut child: Child) -> Option<ExitStatus> {
         use std::time;
 
         const WAIT_DURATION: time::Duration = time::Duration::from_secs(5);
@@ -379,13 +1020,13 @@ impl ServiceInner {
                     Some(pid) => pid,
                     None => {
                         log::error!("service: failed to get pid");
-                        return;
+                        return child.wait().await.ok();
                     }
                 };
 
                 if let Err(_) = signal::kill(Pid::from_raw(pid as i32), Signal::SIGTERM) {
                     let _ = child.kill().await;
-                    return;
+                    return child.wait().await.ok();
                 }
 
                 tokio::select! {
@@ -403,7 +1044,7 @@ impl ServiceInner {
                     Some(pid) => pid,
                     None => {
                         log::error!("service: failed to get pid");
-                        return;
+                        return child.wait().await.ok();
                     }
                 };
 
@@ -427,51 +1068,245 @@ impl ServiceInner {
                 let _ = child.kill().await;
             }
         }
+        child.wait().await.ok()
     }
 
-    async fn clash_api_handle(
-        listen: SocketAddr,
-        secret: Option<String>,
-        started_notify: Arc<Notify>,
+    // Probe the clash api until it answers, fall back to the "sing-box started" log line on timeout
+    #[allow(clippy::too_many_arguments)]
+    async fn ready_handle(
+        client: super::ClashAPIClient,
+        timeout: Duration,
+        mode: Option<database::ClashMode>,
+        selections: Vec<database::ProxySelection>,
+        mut log_started: watch::Receiver<u64>,
+        ready: watch::Sender<bool>,
+        script_handler: Arc<super::ScriptHandler>,
+        run: Arc<RunRecord>,
+        token: CancellationToken,
+        _sender: mpsc::Sender<()>,
+    ) {
+        let probe_ready = tokio::select! {
+            _ = token.cancelled() => return,
+            res = tokio::time::timeout(timeout, client.wait_ready()) => res.is_ok(),
+        };
+        if !probe_ready {
+            log::warn!(
+                "service: clash api is not ready after {}s, waiting for core started log",
+                timeout.as_secs()
+            );
+            tokio::select! {
+                _ = token.cancelled() => return,
+                res = log_started.wait_for(|n| *n > 0) => {
+                    if res.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+        Self::restore_clash_state(&client, mode, selections).await;
+        script_handler.run_after_start_script().await;
+        log::debug!("service: core is ready");
+        ready.send_replace(true);
+        run.set_ready().await;
+    }
+
+    // Saved clash mode and proxy selections of the config, the core starts and reloads with
+    // the defaults of the config
+    async fn restore_clash_state(
+        client: &super::ClashAPIClient,
+        mode: Option<database::ClashMode>,
+        selections: Vec<database::ProxySelection>,
+    ) {
+        if let Some(mode) = mode {
+            if let Err(e) = client.set_mode(&mode.mode).await {
+                log::warn!("service: restore clash mode {} failed: {}", mode.mode, e);
+            }
+        }
+        for selection in selections {
+            if let Err(e) = client
+                .select_proxy(&selection.group, &selection.selected)
+                .await
+            {
+                log::warn!(
+                    "service: restore proxy group {} to {} failed: {}",
+                    selection.group,
+                    selection.selected,
+                    e
+                );
+            }
+        }
+    }
+
+    // Sample the core from /proc, works without the clash api
+    async fn process_handle(
+        pid: u32,
         status: Arc<super::State<Status>>,
         token: CancellationToken,
         _sender: mpsc::Sender<()>,
     ) {
-        // Wait Core Started
-        tokio::select! {
-            _ = started_notify.notified() => {}
-            _ = token.cancelled() => {
-                return;
+        let mut sampler = super::ProcessSampler::new(pid);
+        let mut interval = tokio::time::interval(PROCESS_SAMPLE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = token.cancelled() => return,
             }
+            let sample = match sampler.sample() {
+                Some(v) => v,
+                None => {
+                    log::debug!("service: stop process handle, pid {} is not sampled", pid);
+                    return;
+                }
+            };
+            status
+                .process_cpu_usage
+                .store((sample.cpu_usage * 100.0).round() as u64, Ordering::Relaxed);
+            status.process_memory.store(sample.rss, Ordering::Relaxed);
+            status
+                .process_threads
+                .store(sample.threads, Ordering::Relaxed);
+            status
+                .process_fds
+                .store(sample.fds.unwrap_or(0), Ordering::Relaxed);
+            status
+                .process_uptime
+                .store(sample.uptime, Ordering::Relaxed);
+            status.notify();
         }
-        log::debug!("service: start clash api handle");
-        let (listen_traffic, secret_traffic, token_traffic, status_traffic) = (
-            listen.clone(),
-            secret.clone(),
-            token.clone(),
-            status.clone(),
-        );
-        let (listen_speed, secret_speed, token_speed, status_speed) = (
-            listen.clone(),
-            secret.clone(),
-            token.clone(),
-            status.clone(),
-        );
+    }
+
+    // Write the clash api stats as minute buckets, and prune the old buckets hourly.
+    // The traffic of each bucket is also added to the lifetime totals of the running config,
+    // a flush request writes the samples so far before reload switches the config
+    async fn stat_handle(
+        db: database::Database,
+        name: String,
+        config_id: Arc<RwLock<String>>,
+        mut flush: mpsc::Receiver<oneshot::Sender<()>>,
+        status: Arc<super::State<Status>>,
+        token: CancellationToken,
+        _sender: mpsc::Sender<()>,
+    ) {
+        let mut sampler = super::StatSampler::new(name, chrono::Utc::now().timestamp_millis());
+        let mut interval = tokio::time::interval(STAT_SAMPLE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut pruned = None; // hour of the last prune
+        loop {
+            let done = tokio::select! {
+                _ = interval.tick() => None,
+                Some(done) = flush.recv() => Some(done),
+                _ = token.cancelled() => break,
+            };
+            let now = chrono::Utc::now().timestamp_millis();
+            if status.is_stats_live() {
+                let values = super::StatValues {
+                    upload_total: status.upload_traffic.load(Ordering::Relaxed),
+                    download_total: status.download_traffic.load(Ordering::Relaxed),
+                    upload_speed: status.upload_speed.load(Ordering::Relaxed),
+                    download_speed: status.download_speed.load(Ordering::Relaxed),
+                    memory: status.memory_usage.load(Ordering::Relaxed),
+                    connections: status.connection_count.load(Ordering::Relaxed) as u64,
+                };
+                if let Some(stat) = sampler.sample(now, values) {
+                    Self::save_stat(&db, &config_id, stat).await;
+                    let hour = database::StatGranularity::Hour.bucket(now);
+                    if pruned != Some(hour) {
+                        pruned = Some(hour);
+                        Self::prune_stat(&db, now).await;
+                    }
+                }
+            }
+            if let Some(done) = done {
+                if let Some(stat) = sampler.flush() {
+                    Self::save_stat(&db, &config_id, stat).await;
+                }
+                let _ = done.send(());
+            }
+        }
+        if let Some(stat) = sampler.finish() {
+            Self::save_stat(&db, &config_id, stat).await;
+        }
+    }
+
+    async fn save_stat(db: &database::Database, config_id: &RwLock<String>, stat: database::Stat) {
+        let (upload, download) = (stat.upload, stat.download);
+        if let Err(e) = database::add_stat(db, stat).await {
+            log::error!("service: add stat failed: {}", e);
+        }
+        let now = chrono::Utc::now().timestamp_millis();
+        let config_id = config_id.read().unwrap().clone();
+        if let Err(e) = database::add_traffic_total(db, config_id, upload, download, now).await {
+            log::error!("service: add traffic total failed: {}", e);
+        }
+    }
+
+    async fn prune_stat(db: &database::Database, now: i64) {
+        let retention = match database::get_stat_retention(db).await {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("service: get stat retention failed: {}", e);
+                return;
+            }
+        };
+        for granularity in database::StatGranularity::ALL {
+            let before = now - retention.get(granularity).as_millis() as i64;
+            match database::prune_stat(db, granularity, before).await {
+                Ok(0) => {}
+                Ok(n) => log::debug!("service: pruned {} {} stats", n, granularity.as_str()),
+                Err(e) => log::error!("service: prune stat failed: {}", e),
+            }
+        }
+    }
+
+    async fn clash_api_handle(
+        listen: SocketAddr,
+        secret: Option<String>,
+        mut ready: watch::Receiver<bool>,
+        status: Arc<super::State<Status>>,
+        token: CancellationToken,
+        _sender: mpsc::Sender<()>,
+    ) {
+        // Wait Core Ready
+        tokio::select! {
+            res = ready.wait_for(|v| *v) => {
+                if res.is_err() {
+                    return;
+                }
+            }
+            _ = token.cancelled() => {
+                return;
+            }
+        }
+        log::debug!("service: start clash api handle");
+        let (listen_traffic, secret_traffic, token_traffic, status_traffic) = (
+            listen.clone(),
+            secret.clone(),
+            token.clone(),
+            status.clone(),
+        );
+        let (listen_speed, secret_speed, token_speed, status_speed) = (
+            listen.clone(),
+            secret.clone(),
+            token.clone(),
+            status.clone(),
+        );
         let (listen_memory, secret_memory, token_memory, status_memory) =
             (listen, secret, token, status);
         let fut_traffic = async move {
             log::debug!("service: start clash api traffic handle");
             super::ClashAPIType::Traffic
-                .handle::<_, super::ClashAPITrafficResult, _, _>(
+                .handle::<_, super::ClashAPITrafficResult, _, _, _>(
                     listen_traffic,
                     secret_traffic,
                     token_traffic,
                     status_traffic,
                     |status, data| async move {
-                        status.connection_count.store(
-                            data.connections.map(|c| c.len()).unwrap_or(0),
-                            Ordering::Relaxed,
-                        );
+                        let connections = data.connections.unwrap_or_default();
+                        status
+                            .connection_count
+                            .store(connections.len(), Ordering::Relaxed);
+                        *status.connections.write().unwrap() = Arc::new(connections);
                         status
                             .upload_traffic
                             .store(data.upload_traffic, Ordering::Relaxed);
@@ -480,6 +1315,10 @@ impl ServiceInner {
                             .store(data.download_traffic, Ordering::Relaxed);
                         status.notify();
                     },
+                    |status, live| {
+                        status.traffic_live.store(live, Ordering::Relaxed);
+                        status.notify();
+                    },
                 )
                 .await;
             log::debug!("service: stop clash api traffic handle");
@@ -487,7 +1326,7 @@ impl ServiceInner {
         let fut_speed = async move {
             log::debug!("service: start clash api speed handle");
             super::ClashAPIType::Speed
-                .handle::<_, super::ClashAPISpeedResult, _, _>(
+                .handle::<_, super::ClashAPISpeedResult, _, _, _>(
                     listen_speed,
                     secret_speed,
                     token_speed,
@@ -501,6 +1340,10 @@ impl ServiceInner {
                             .store(data.download_speed, Ordering::Relaxed);
                         status.notify();
                     },
+                    |status, live| {
+                        status.speed_live.store(live, Ordering::Relaxed);
+                        status.notify();
+                    },
                 )
                 .await;
             log::debug!("service: stop clash api speed handle");
@@ -508,7 +1351,7 @@ impl ServiceInner {
         let fut_memory = async move {
             log::debug!("service: start clash api memory handle");
             super::ClashAPIType::Memory
-                .handle::<_, super::ClashAPIMemoryResult, _, _>(
+                .handle::<_, super::ClashAPIMemoryResult, _, _, _>(
                     listen_memory,
                     secret_memory,
                     token_memory,
@@ -517,6 +1360,10 @@ impl ServiceInner {
                         status.memory_usage.store(data.memory, Ordering::Relaxed);
                         status.notify();
                     },
+                    |status, live| {
+                        status.memory_live.store(live, Ordering::Relaxed);
+                        status.notify();
+                    },
                 )
                 .await;
             log::debug!("service: stop clash api memory handle");
@@ -525,20 +1372,39 @@ impl ServiceInner {
     }
 }
 
+// Applied config which is reverted unless confirmed before the deadline
+struct PendingConfirm {
+    config: database::Config,
+    previous: Option<database::Config>,
+    token: CancellationToken,
+}
+
 #[derive(Clone)]
 pub(crate) struct Service {
     manager: Arc<Manager>,
+    name: String, // instance name
     inner: Arc<Mutex<Option<ServiceInner>>>,
+    generation: Arc<AtomicU64>,
+    inner_id: Arc<AtomicU64>,
+    last_good: Arc<RwLock<Option<database::Config>>>,
+    pending_confirm: Arc<RwLock<Option<PendingConfirm>>>,
     log_queue: Arc<super::LogQueue<String>>,
     status: Arc<super::State<Status>>,
+    clash_api: Arc<RwLock<Option<ClashAPISnapshot>>>,
 }
 
 impl Service {
-    pub(crate) fn new(manager: Arc<Manager>) -> Self {
+    pub(crate) fn new(manager: Arc<Manager>, name: String) -> Self {
         Self {
             manager,
+            name,
             inner: Arc::new(Mutex::new(None)),
+            generation: Arc::new(AtomicU64::new(0)),
+            inner_id: Arc::new(AtomicU64::new(0)),
+            last_good: Arc::new(RwLock::new(None)),
+            pending_confirm: Arc::new(RwLock::new(None)),
             log_queue: Arc::new(super::LogQueue::new(16)),
+            clash_api: Arc::new(RwLock::new(None)),
             status: Arc::new(super::State::new(
                 Status::default(),
                 Arc::new(Notify::new()),
@@ -549,9 +1415,19 @@ impl Service {
     pub(crate) async fn start(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
         let db = self.manager.get_database();
         // Auto Start
-        if let Ok(b) = database::get_auto_start(&db).await {
+        let auto_start = if self.is_default() {
+            database::get_auto_start(&db).await
+        } else {
+            database::get_instance(&db, self.name.clone())
+                .await
+                .map(|instance| instance.auto_start)
+        };
+        if let Ok(b) = auto_start {
             if b {
-                if let Err(e) = self.start_service().await {
+                if let Err(e) = self
+                    .restart_with_reason(None, database::RunReason::AutoStart)
+                    .await
+                {
                     log::error!("service: auto start failed: {}", e);
                 }
             }
@@ -559,65 +1435,923 @@ impl Service {
         Ok(())
     }
 
+    // Wait for the in-flight transition instead of rejecting
     pub(crate) async fn close(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
-        if let Err(e) = self.stop_service().await {
-            log::error!("service: close failed: {}", e);
+        let mut inner_lock = self.inner.lock().await;
+        self.take_pending_confirm();
+        self.stop_inner(&mut inner_lock, database::RunReason::Shutdown)
+            .await;
+        Ok(())
+    }
+
+    pub(crate) fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn is_default(&self) -> bool {
+        self.name == database::DEFAULT_INSTANCE
+    }
+
+    async fn get_core_path(&self) -> Result<String, super::Error> {
+        let db = self.manager.get_database();
+        if !self.is_default() {
+            let instance = database::get_instance(&db, self.name.clone())
+                .await
+                .map_err(|e| super::Error::GetCorePathFailed(e.to_string()))?;
+            if let Some(p) = instance.core_path {
+                return Ok(p);
+            }
+        }
+        match database::get_core_path(&db).await {
+            Ok(Some(p)) => Ok(p),
+            Ok(None) => Err(super::Error::CorePathNotSet),
+            Err(e) => Err(super::Error::GetCorePathFailed(e.to_string())),
         }
+    }
+
+    async fn get_active_config(&self) -> Result<database::Config, super::Error> {
+        let db = self.manager.get_database();
+        if self.is_default() {
+            return match database::get_active_config(&db).await {
+                Ok(Some(m)) => Ok(m),
+                Ok(None) => Err(super::Error::ConfigNotSet),
+                Err(e) => Err(super::Error::GetConfigFailed(e.to_string())),
+            };
+        }
+        let instance = database::get_instance(&db, self.name.clone())
+            .await
+            .map_err(|e| super::Error::GetConfigFailed(e.to_string()))?;
+        match instance.config_id {
+            Some(id) => database::get_config(&db, id)
+                .await
+                .map_err(|e| super::Error::GetConfigFailed(e.to_string())),
+            None => Err(super::Error::ConfigNotSet),
+        }
+    }
+
+    async fn set_active_config(&self, id: String) -> Result<(), database::Error> {
+        let db = self.manager.get_database();
+        if self.is_default() {
+            database::set_active_config(&db, id).await
+        } else {
+            database::set_instance_config(&db, self.name.clone(), id).await
+        }
+    }
+
+    pub(crate) async fn start_service(&self, wait: Option<Duration>) -> Result<(), super::Error> {
+        self.restart_service(wait).await
+    }
+
+    // Take the lifecycle lock, reject the command while another transition is in flight
+    fn lock_inner(&self) -> Result<MutexGuard<'_, Option<ServiceInner>>, super::Error> {
+        self.inner
+            .try_lock()
+            .map_err(|_| super::Error::Busy(self.status.get_state()))
+    }
+
+    fn set_state(&self, state: ServiceState) {
+        if self.status.set_state(state) {
+            log::debug!("service: state: {}", state);
+            self.status.notify();
+        }
+    }
+
+    fn set_last_exit(&self, reason: String) {
+        *self.status.last_exit_reason.write().unwrap() = reason;
+        self.status
+            .last_exit_time
+            .store(chrono::Utc::now().timestamp() as u64, Ordering::Relaxed);
+        self.status.notify();
+    }
+
+    pub(crate) async fn stop_service(&self) -> Result<(), super::Error> {
+        let mut inner_lock = self.lock_inner()?;
+        self.take_pending_confirm();
+        self.stop_inner(&mut inner_lock, database::RunReason::Api)
+            .await;
         Ok(())
     }
 
-    async fn get_start_prepare_info(&self) -> Result<(String, database::Config), super::Error> {
+    async fn stop_inner(&self, inner: &mut Option<ServiceInner>, reason: database::RunReason) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        self.status.is_restarting.store(false, Ordering::Relaxed);
+        if let Some(mut inner) = inner.take() {
+            self.publish_clash_api(&None);
+            self.set_state(ServiceState::Stopping);
+            inner.cancel_and_wait(reason).await;
+            self.set_last_exit("stopped".to_string());
+        }
+        self.set_state(ServiceState::Stopped);
+    }
+
+    // Wait: wait until the core is ready or has exited
+    pub(crate) async fn restart_service(&self, wait: Option<Duration>) -> Result<(), super::Error> {
+        self.restart_with_reason(wait, database::RunReason::Api)
+            .await
+    }
+
+    async fn restart_with_reason(
+        &self,
+        wait: Option<Duration>,
+        reason: database::RunReason,
+    ) -> Result<(), super::Error> {
+        let mut inner_lock = self.lock_inner()?;
+        self.take_pending_confirm();
+        let config = self.get_active_config().await?;
+        self.start_with_rollback(&mut inner_lock, config, wait, reason)
+            .await
+    }
+
+    // Reload the active config in place by SIGHUP, restart when a reload is impossible
+    // Wait: wait until the core is ready or has exited (restart only)
+    pub(crate) async fn reload_service(&self, wait: Option<Duration>) -> Result<(), super::Error> {
+        let mut inner_lock = self.lock_inner()?;
+        self.take_pending_confirm();
+        let config = self.get_active_config().await?;
+        let previous_state = self.status.get_state();
+        match self.reload_inner(&mut inner_lock, config.clone()).await {
+            Ok(true) => {
+                self.set_state(ServiceState::Running);
+                return Ok(());
+            }
+            Ok(false) => {}
+            Err(e) => {
+                self.set_state(previous_state);
+                return Err(e);
+            }
+        }
+        log::info!("service: reload is not possible, restarting");
+        self.start_with_rollback(&mut inner_lock, config, wait, database::RunReason::Api)
+            .await
+    }
+
+    // Return false if the running core can not take the config by reload
+    async fn reload_inner(
+        &self,
+        inner_lock: &mut Option<ServiceInner>,
+        config: database::Config,
+    ) -> Result<bool, super::Error> {
+        let inner = match inner_lock.as_mut() {
+            Some(inner) if !inner.token.is_cancelled() => inner,
+            _ => return Ok(false),
+        };
+        let core_path = self.get_core_path().await?;
+        if core_path != inner.core_path {
+            return Ok(false);
+        }
+        // Process options can only be applied by a new process
+        let options = self.get_process_options(&config).await?;
+        if options != inner.options {
+            return Ok(false);
+        }
+        // Keep the generated secret, the clash api handlers are still using it
+        let prepared =
+            ServiceInner::prepare(core_path, config.clone(), options, inner.secret.clone())
+                .await
+                .map_err(|e| {
+                    log::error!("service: prepare config failed: {}", e);
+                    super::Error::StartServiceFailed(e.to_string())
+                })?;
+        if prepared.listen != inner.listen || prepared.secret != inner.secret {
+            return Ok(false);
+        }
+        self.set_state(ServiceState::Reloading);
+        ServiceInner::check_core_config(&prepared)
+            .await
+            .map_err(|e| {
+                log::error!("service: check config failed: {}", e);
+                self.log_queue.push_data(format!(
+                    "[{}] check config failed: {}",
+                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                    e
+                ));
+                super::Error::CheckConfigFailed(e)
+            })?;
+        ServiceInner::write_config_file(&inner.config_path, &prepared.config.config)
+            .await
+            .map_err(|e| super::Error::StartServiceFailed(e.to_string()))?;
+        // The traffic so far belongs to the old config
+        inner.flush_stat().await;
+        let started = *inner.log_started.borrow();
+        if !inner.reload() {
+            return Ok(false);
+        }
+        *inner.stat_config_id.write().unwrap() = config.id.clone();
+        log::info!("service: config reloaded: {}", config.tag);
+        self.log_queue.push_data(format!(
+            "[{}] service is reloaded with config: {}",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            config.tag
+        ));
+        inner.config = prepared.config;
+        inner.source = prepared.source;
+        *self.status.running_config.write().unwrap() = config.tag;
+        self.status.notify();
+        self.publish_clash_api(inner_lock);
+        if let Some(inner) = inner_lock.as_mut() {
+            self.restore_after_reload(inner, started).await;
+        }
+        Ok(true)
+    }
+
+    // The core rebuilds its box on reload, so the saved state of the (maybe switched) config is
+    // applied again once the new clash api answers. The old one keeps answering while the core
+    // checks the config, the next "sing-box started" log line tells they are swapped.
+    async fn restore_after_reload(&self, inner: &mut ServiceInner, started: u64) {
         let db = self.manager.get_database();
-        let core_path = match database::get_core_path(&db).await {
-            Ok(Some(p)) => p,
-            Ok(None) => return Err(super::Error::CorePathNotSet),
-            Err(e) => return Err(super::Error::GetCorePathFailed(e.to_string())),
+        let timeout = match database::get_ready_timeout(&db).await {
+            Ok(v) => Duration::from_secs(v),
+            Err(e) => {
+                log::error!("service: get ready timeout failed: {}", e);
+                return;
+            }
         };
-        let config = match database::get_active_config(&db).await {
-            Ok(Some(m)) => m,
-            Ok(None) => return Err(super::Error::ConfigNotSet),
-            Err(e) => return Err(super::Error::GetConfigFailed(e.to_string())),
+        let reloaded = tokio::time::timeout(timeout, async {
+            let _ = inner.log_started.wait_for(|n| *n > started).await;
+            inner.clash_api.wait_ready().await;
+        })
+        .await;
+        if reloaded.is_err() {
+            log::warn!(
+                "service: clash api is not reloaded after {}s, restoring anyway",
+                timeout.as_secs()
+            );
+        }
+        let mode = database::get_clash_mode(&db, inner.source.id.clone())
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("service: get clash mode failed: {}", e);
+                None
+            });
+        let selections = database::list_proxy_selection(&db, inner.source.id.clone())
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("service: list proxy selection failed: {}", e);
+                Vec::new()
+            });
+        ServiceInner::restore_clash_state(&inner.clash_api, mode, selections).await;
+    }
+
+    // Global options merged with the config ones, the working dir defaults to data_dir/configs/<id>
+    async fn get_launch_option(
+        &self,
+        config: &database::Config,
+    ) -> Result<database::LaunchOption, super::Error> {
+        let db = self.manager.get_database();
+        let global = database::get_launch_option(&db)
+            .await
+            .map_err(|e| super::Error::GetLaunchOptionFailed(e.to_string()))?;
+        let mut launch = match database::get_config_launch_option(&db, config.id.clone())
+            .await
+            .map_err(|e| super::Error::GetLaunchOptionFailed(e.to_string()))?
+        {
+            Some(option) => global.merge(option),
+            None => global,
         };
-        Ok((core_path, config))
+        let data_dir = self.data_dir_path();
+        let working_dir = match &launch.working_dir {
+            Some(dir) => data_dir.join(dir), // relative to data dir
+            None => data_dir.join("configs").join(&config.id),
+        };
+        launch.working_dir = Some(working_dir.to_string_lossy().to_string());
+        Ok(launch)
     }
 
-    pub(crate) async fn start_service(&self) -> Result<(), super::Error> {
-        self.restart_service().await
+    async fn get_process_options(
+        &self,
+        config: &database::Config,
+    ) -> Result<ProcessOptions, super::Error> {
+        let db = self.manager.get_database();
+        let launch = self.get_launch_option(config).await?;
+        let limit = database::get_process_limit(&db, self.name.clone())
+            .await
+            .map_err(|e| super::Error::GetProcessLimitFailed(e.to_string()))?;
+        let netns = database::get_netns(&db, self.name.clone())
+            .await
+            .map_err(|e| super::Error::GetNetnsFailed(e.to_string()))?;
+        Ok(ProcessOptions {
+            launch,
+            limit,
+            netns,
+        })
     }
 
-    pub(crate) async fn stop_service(&self) -> Result<(), super::Error> {
-        let mut inner_lock = self.inner.lock().await;
-        if let Some(mut inner) = inner_lock.take() {
-            inner.cancel_and_wait().await;
+    fn data_dir_path(&self) -> PathBuf {
+        let data_dir = self.manager.get_data_dir_path();
+        std::path::absolute(data_dir).unwrap_or_else(|_| data_dir.clone())
+    }
+
+    fn config_file_path(&self) -> PathBuf {
+        let filename = if self.is_default() {
+            "running_config.json".to_string()
+        } else {
+            format!("running_config_{}.json", self.name)
+        };
+        self.data_dir_path().join(filename)
+    }
+
+    // Start the given config and mark it as active once it is ready
+    // Confirm: keep the previous config active and revert to it unless confirmed in time
+    pub(crate) async fn apply_config(
+        &self,
+        id: String,
+        timeout: Duration,
+        confirm: Option<Duration>,
+    ) -> Result<(), super::Error> {
+        let mut inner_lock = self.lock_inner()?;
+        let db = self.manager.get_database();
+        let config = database::get_config(&db, id.clone())
+            .await
+            .map_err(|e| super::Error::GetConfigFailed(e.to_string()))?;
+        let window = match confirm {
+            Some(window) => window,
+            None => {
+                self.take_pending_confirm();
+                self.start_with_rollback(
+                    &mut inner_lock,
+                    config,
+                    Some(timeout),
+                    database::RunReason::Api,
+                )
+                .await?;
+                return self
+                    .set_active_config(id)
+                    .await
+                    .map_err(|e| super::Error::SetActiveConfigFailed(e.to_string()));
+            }
+        };
+        // The previous config stays the revert target when applying over a pending one
+        let previous = match self.take_pending_confirm() {
+            Some(pending) => pending.previous,
+            None => match Self::is_running(&inner_lock) {
+                true => self.last_good.read().unwrap().clone(),
+                false => None,
+            },
+        };
+        let token = CancellationToken::new();
+        *self.pending_confirm.write().unwrap() = Some(PendingConfirm {
+            config: config.clone(),
+            previous,
+            token: token.clone(),
+        });
+        if let Err(e) = self
+            .start_with_rollback(
+                &mut inner_lock,
+                config.clone(),
+                Some(timeout),
+                database::RunReason::Api,
+            )
+            .await
+        {
+            self.take_pending_confirm();
+            return Err(e);
         }
+        let deadline = chrono::Utc::now().timestamp() as u64 + window.as_secs();
+        *self.status.confirm_config.write().unwrap() = config.tag.clone();
+        self.status
+            .confirm_deadline
+            .store(deadline, Ordering::Relaxed);
+        self.status.notify();
+        log::info!(
+            "service: config {} is pending confirmation for {}s",
+            config.tag,
+            window.as_secs()
+        );
+        self.log_queue.push_data(format!(
+            "[{}] config {} is pending confirmation for {}s",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            config.tag,
+            window.as_secs()
+        ));
+        let service = self.clone();
+        tokio::spawn(async move { service.confirm_handle(token, window).await });
         Ok(())
     }
 
-    pub(crate) async fn restart_service(&self) -> Result<(), super::Error> {
+    // Confirm the applied config: mark it as active and stop the revert timer
+    pub(crate) async fn confirm_config(&self) -> Result<(), super::Error> {
+        let pending = self
+            .take_pending_confirm()
+            .ok_or(super::Error::NoPendingConfirm)?;
+        self.set_active_config(pending.config.id.clone())
+            .await
+            .map_err(|e| super::Error::SetActiveConfigFailed(e.to_string()))?;
+        log::info!("service: config {} is confirmed", pending.config.tag);
+        self.log_queue.push_data(format!(
+            "[{}] config {} is confirmed",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            pending.config.tag
+        ));
+        if Self::is_running(&*self.inner.lock().await) {
+            self.set_last_good(pending.config);
+        }
+        Ok(())
+    }
+
+    fn take_pending_confirm(&self) -> Option<PendingConfirm> {
+        let pending = self.pending_confirm.write().unwrap().take()?;
+        pending.token.cancel();
+        self.clean_confirm_status();
+        Some(pending)
+    }
+
+    fn clean_confirm_status(&self) {
+        self.status.confirm_config.write().unwrap().clear();
+        self.status.confirm_deadline.store(0, Ordering::Relaxed);
+        self.status.notify();
+    }
+
+    async fn confirm_handle(&self, token: CancellationToken, window: Duration) {
+        const TICK: Duration = Duration::from_secs(1);
+
+        let deadline = Instant::now() + window;
+        while Instant::now() < deadline {
+            tokio::select! {
+                _ = token.cancelled() => return,
+                _ = tokio::time::sleep(TICK.min(deadline - Instant::now())) => {}
+            }
+            // Refresh the countdown
+            self.status.notify();
+        }
+        let pending = {
+            let mut pending_confirm = self.pending_confirm.write().unwrap();
+            if token.is_cancelled() {
+                return;
+            }
+            pending_confirm.take()
+        };
+        let pending = match pending {
+            Some(v) => v,
+            None => return,
+        };
+        self.clean_confirm_status();
+        let previous_tag = match &pending.previous {
+            Some(c) => c.tag.clone(),
+            None => "(stopped)".to_string(),
+        };
+        let message = format!(
+            "config {} was not confirmed within {}s, reverted to {}",
+            pending.config.tag,
+            window.as_secs(),
+            previous_tag
+        );
+        log::warn!("service: {}", message);
+        self.log_queue.push_data(format!(
+            "[{}] {}",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            message
+        ));
         let mut inner_lock = self.inner.lock().await;
-        if let Some(mut inner) = inner_lock.take() {
-            inner.cancel_and_wait().await;
+        let result = match pending.previous {
+            Some(previous) => {
+                let timeout = database::get_ready_timeout(&self.manager.get_database())
+                    .await
+                    .unwrap_or(30);
+                self.start_with_rollback(
+                    &mut inner_lock,
+                    previous,
+                    Some(Duration::from_secs(timeout)),
+                    database::RunReason::Revert,
+                )
+                .await
+            }
+            None => {
+                self.stop_inner(&mut inner_lock, database::RunReason::Revert)
+                    .await;
+                Ok(())
+            }
+        };
+        match result {
+            Ok(_) => *self.status.last_failure.write().unwrap() = message,
+            Err(e) => {
+                log::error!("service: revert config failed: {}", e);
+                *self.status.last_failure.write().unwrap() = format!(
+                    "{}, but revert failed: {}",
+                    message,
+                    e.to_string().lines().next().unwrap_or_default()
+                );
+            }
+        }
+        self.status.notify();
+    }
+
+    fn is_running(inner: &Option<ServiceInner>) -> bool {
+        match inner {
+            Some(inner) => !inner.token.is_cancelled(),
+            None => false,
+        }
+    }
+
+    async fn start_with_rollback(
+        &self,
+        inner_lock: &mut Option<ServiceInner>,
+        config: database::Config,
+        wait: Option<Duration>,
+        reason: database::RunReason,
+    ) -> Result<(), super::Error> {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        // Only a running core is rolled back to, a stopped or crashed one was not asked for
+        if !Self::is_running(inner_lock) {
+            *self.last_good.write().unwrap() = None;
+        }
+        self.status.restart_count.store(0, Ordering::Relaxed);
+        self.status.is_restarting.store(false, Ordering::Relaxed);
+        self.status.last_failure.write().unwrap().clear();
+        self.status.notify();
+        let previous_state = self.status.get_state();
+        self.set_state(ServiceState::Starting);
+        if let Err(e) = self
+            .start_inner(inner_lock, generation, config.clone(), reason)
+            .await
+        {
+            // The running instance is untouched when the new one fails before launch
+            if inner_lock.is_some() {
+                self.set_state(previous_state);
+                return Err(e);
+            }
+            return Err(self.rollback(inner_lock, generation, &config, e).await);
+        }
+        let timeout = match wait {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        let result = match inner_lock.as_ref() {
+            Some(inner) => self.wait_ready(inner, timeout).await,
+            None => Ok(()),
+        };
+        match result {
+            Ok(_) => {
+                self.set_state(ServiceState::Running);
+                self.set_last_good(config);
+                Ok(())
+            }
+            Err(e) => Err(self.rollback(inner_lock, generation, &config, e).await),
+        }
+    }
+
+    async fn wait_ready(
+        &self,
+        inner: &ServiceInner,
+        timeout: Duration,
+    ) -> Result<(), super::Error> {
+        const LOG_LINES: usize = 10;
+
+        let mut ready = inner.ready.clone();
+        tokio::select! {
+            _ = ready.wait_for(|v| *v) => {}
+            _ = inner.token.cancelled() => {}
+            _ = tokio::time::sleep(timeout) => {
+                return Err(super::Error::ServiceNotReady(
+                    timeout.as_secs(),
+                    self.log_queue.recent(LOG_LINES),
+                ));
+            }
+        }
+        if *ready.borrow() {
+            Ok(())
+        } else {
+            Err(super::Error::ServiceExited(
+                self.log_queue.recent(LOG_LINES),
+            ))
         }
-        let (core_path, config) = self.get_start_prepare_info().await.map_err(|e| {
+    }
+
+    fn set_last_good(&self, config: database::Config) {
+        // An unconfirmed config is never a rollback target
+        if let Some(pending) = self.pending_confirm.read().unwrap().as_ref() {
+            if pending.config == config {
+                return;
+            }
+        }
+        log::debug!("service: last known good config: {}", config.tag);
+        *self.last_good.write().unwrap() = Some(config);
+    }
+
+    // Relaunch the last config which reached ready state, return the error to report
+    async fn rollback(
+        &self,
+        inner: &mut Option<ServiceInner>,
+        generation: u64,
+        failed_config: &database::Config,
+        err: super::Error,
+    ) -> super::Error {
+        let last_good = match self.last_good.read().unwrap().clone() {
+            Some(c) if &c != failed_config => c,
+            _ => {
+                if inner.is_none() {
+                    self.set_state(ServiceState::Stopped);
+                }
+                return err;
+            }
+        };
+        self.take_pending_confirm();
+        let err_string = err.to_string();
+        let failure = format!(
+            "config {} failed: {}",
+            failed_config.tag,
+            err_string.lines().next().unwrap_or_default()
+        );
+        *self.status.last_failure.write().unwrap() = failure.clone();
+        self.status.notify();
+        log::error!(
+            "service: {}, rolling back to config: {}",
+            failure,
+            last_good.tag
+        );
+        self.log_queue.push_data(format!(
+            "[{}] {}, rolling back to config: {}",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            failure,
+            last_good.tag
+        ));
+        if let Some(mut inner) = inner.take() {
+            self.publish_clash_api(&None);
+            inner.cancel_and_wait(database::RunReason::Rollback).await;
+        }
+        if let Err(e) = self
+            .start_inner(
+                inner,
+                generation,
+                last_good.clone(),
+                database::RunReason::Rollback,
+            )
+            .await
+        {
+            self.set_state(ServiceState::Stopped);
+            log::error!("service: rollback failed: {}", e);
+            return super::Error::RollbackFailed(err_string, last_good.tag, e.to_string());
+        }
+        if last_good.id != failed_config.id {
+            if let Err(e) = self.set_active_config(last_good.id.clone()).await {
+                log::error!("service: restore active config failed: {}", e);
+            }
+        }
+        super::Error::RolledBack(err_string, last_good.tag)
+    }
+
+    async fn start_inner(
+        &self,
+        inner: &mut Option<ServiceInner>,
+        generation: u64,
+        config: database::Config,
+        reason: database::RunReason, // also the stop reason of the replaced instance
+    ) -> Result<(), super::Error> {
+        let core_path = self.get_core_path().await.map_err(|e| {
             log::error!("service: prepare info failed: {}", e);
             super::Error::StartServiceFailed(e.to_string())
         })?;
-        let inner = ServiceInner::new(
+        let options = self.get_process_options(&config).await?;
+        let prepared = ServiceInner::prepare(core_path, config.clone(), options, None)
+            .await
+            .map_err(|e| {
+                log::error!("service: prepare config failed: {}", e);
+                super::Error::StartServiceFailed(e.to_string())
+            })?;
+        // Keep the running instance if the new config is rejected by the core
+        ServiceInner::check_core_config(&prepared)
+            .await
+            .map_err(|e| {
+                log::error!("service: check config failed: {}", e);
+                self.log_queue.push_data(format!(
+                    "[{}] check config failed: {}",
+                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                    e
+                ));
+                super::Error::CheckConfigFailed(e)
+            })?;
+        let replaced = match inner.take() {
+            Some(mut inner) => {
+                self.publish_clash_api(&None);
+                inner.cancel_and_wait(reason).await;
+                true
+            }
+            None => false,
+        };
+        self.set_state(ServiceState::Starting);
+        let id = self.inner_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut new_inner = ServiceInner::new(
             self.manager.clone(),
-            core_path,
-            config,
+            &self.name,
+            id,
+            prepared,
+            self.config_file_path(),
+            reason,
             self.log_queue.clone(),
             self.status.clone(),
         )
         .await
         .map_err(|e| {
             log::error!("service: start service failed: {}", e);
+            self.set_state(ServiceState::Stopped);
             super::Error::StartServiceFailed(e.to_string())
         })?;
-        inner_lock.replace(inner);
+        if let Some(exit_receiver) = new_inner.exit_receiver.take() {
+            self.spawn_exit_handle(
+                generation,
+                id,
+                config,
+                reason,
+                new_inner.ready.clone(),
+                exit_receiver,
+            );
+        }
+        inner.replace(new_inner);
+        self.publish_clash_api(inner);
+        if replaced || reason == database::RunReason::AutoRestart {
+            self.status.restart_total.fetch_add(1, Ordering::Relaxed);
+        }
         Ok(())
     }
 
+    fn spawn_exit_handle(
+        &self,
+        generation: u64,
+        id: u64,
+        config: database::Config,
+        reason: database::RunReason,
+        ready: watch::Receiver<bool>,
+        exit_receiver: oneshot::Receiver<ServiceExit>,
+    ) {
+        let service = self.clone();
+        tokio::spawn(Box::pin(async move {
+            service
+                .exit_handle(generation, id, config, reason, ready, exit_receiver)
+                .await
+        }));
+    }
+
+    // Check the service is still in the state the handler last saw
+    fn is_current(&self, inner: &Option<ServiceInner>, generation: u64, id: Option<u64>) -> bool {
+        self.generation.load(Ordering::Relaxed) == generation
+            && inner.as_ref().map(|inner| inner.id) == id
+    }
+
+    // Roll back when the core exits before ready, otherwise apply the restart policy.
+    // A requested start which never got ready is reported to the caller and not restarted
+    async fn exit_handle(
+        &self,
+        generation: u64,
+        id: u64,
+        mut config: database::Config,
+        reason: database::RunReason,
+        mut ready: watch::Receiver<bool>,
+        exit_receiver: oneshot::Receiver<ServiceExit>,
+    ) {
+        let is_ready = ready.wait_for(|v| *v).await.is_ok();
+        if is_ready {
+            self.set_last_good(config.clone());
+            let inner_lock = self.inner.lock().await;
+            if self.is_current(&inner_lock, generation, Some(id)) {
+                self.set_state(ServiceState::Running);
+            }
+        }
+        let (mut exit_status, mut uptime) = match exit_receiver.await {
+            Ok(ServiceExit::Exited { status, uptime }) => (status, uptime),
+            _ => return,
+        };
+        let mut expected_id = Some(id);
+        {
+            let inner_lock = self.inner.lock().await;
+            if !self.is_current(&inner_lock, generation, expected_id) {
+                return;
+            }
+            let failed = !exit_status.map(|s| s.success()).unwrap_or(false);
+            self.set_last_exit(format!(
+                "exited ({}) after {}s",
+                match exit_status {
+                    Some(s) => s.to_string(),
+                    None => "unknown exit status".to_string(),
+                },
+                uptime.as_secs()
+            ));
+            self.set_state(match failed {
+                true => ServiceState::Crashed,
+                false => ServiceState::Stopped,
+            });
+        }
+        if !is_ready {
+            let mut inner_lock = self.inner.lock().await;
+            if !self.is_current(&inner_lock, generation, expected_id) {
+                return;
+            }
+            let has_last_good = matches!(&*self.last_good.read().unwrap(), Some(c) if c != &config);
+            if has_last_good {
+                const LOG_LINES: usize = 10;
+
+                let err = super::Error::ServiceExited(self.log_queue.recent(LOG_LINES));
+                self.rollback(&mut inner_lock, generation, &config, err)
+                    .await;
+                return;
+            }
+            if reason != database::RunReason::AutoRestart {
+                log::error!("service: service exited before ready, not restarting");
+                self.log_queue.push_data(format!(
+                    "[{}] service exited before ready, not restarting",
+                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                ));
+                return;
+            }
+        }
+        loop {
+            let policy = match database::get_restart_policy(&self.manager.get_database()).await {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("service: get restart policy failed: {}", e);
+                    return;
+                }
+            };
+            if uptime >= Duration::from_secs(policy.reset_window) {
+                self.status.restart_count.store(0, Ordering::Relaxed);
+            }
+            let failed = !exit_status.map(|s| s.success()).unwrap_or(false);
+            let should_restart = match policy.mode {
+                database::RestartMode::Never => false,
+                database::RestartMode::OnFailure => failed,
+                database::RestartMode::Always => true,
+            };
+            if !should_restart {
+                return;
+            }
+            let attempt = self.status.restart_count.load(Ordering::Relaxed) as u32 + 1;
+            if policy.max_retries > 0 && attempt > policy.max_retries {
+                log::error!(
+                    "service: restart limit reached: {} retries",
+                    policy.max_retries
+                );
+                self.log_queue.push_data(format!(
+                    "[{}] service restart limit reached ({} retries), giving up",
+                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                    policy.max_retries
+                ));
+                return;
+            }
+            let delay = policy.backoff(attempt);
+            let exit_message = match exit_status {
+                Some(s) => s.to_string(),
+                None => "unknown exit status".to_string(),
+            };
+            {
+                let inner_lock = self.inner.lock().await;
+                if !self.is_current(&inner_lock, generation, expected_id) {
+                    return;
+                }
+                self.status
+                    .restart_count
+                    .store(attempt as u64, Ordering::Relaxed);
+                self.status.is_restarting.store(true, Ordering::Relaxed);
+                self.status.notify();
+                self.set_state(ServiceState::Restarting);
+            }
+            log::warn!(
+                "service: service exited unexpectedly ({}), restarting in {}s (attempt {})",
+                exit_message,
+                delay.as_secs(),
+                attempt
+            );
+            self.log_queue.push_data(format!(
+                "[{}] service exited unexpectedly ({}), restarting in {}s (attempt {})",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                exit_message,
+                delay.as_secs(),
+                attempt
+            ));
+            tokio::time::sleep(delay).await;
+            let mut inner_lock = self.inner.lock().await;
+            if !self.is_current(&inner_lock, generation, expected_id) {
+                return;
+            }
+            self.status.is_restarting.store(false, Ordering::Relaxed);
+            self.status.notify();
+            // The config may have been reloaded since launch
+            if let Some(inner) = inner_lock.as_ref() {
+                config = inner.source.clone();
+            }
+            match self
+                .start_inner(
+                    &mut inner_lock,
+                    generation,
+                    config.clone(),
+                    database::RunReason::AutoRestart,
+                )
+                .await
+            {
+                Ok(_) => return,
+                Err(e) => {
+                    self.set_state(ServiceState::Crashed);
+                    log::error!("service: auto restart failed: {}", e);
+                    self.log_queue.push_data(format!(
+                        "[{}] service auto restart failed: {}",
+                        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        e
+                    ));
+                    expected_id = inner_lock.as_ref().map(|inner| inner.id);
+                    exit_status = None;
+                    uptime = Duration::ZERO;
+                }
+            }
+        }
+    }
+
     pub(crate) async fn get_config(&self) -> Option<serde_json::Value> {
         self.inner
             .lock()
@@ -626,6 +2360,225 @@ impl Service {
             .map(|inner| inner.config.config.clone())
     }
 
+    // Called whenever the lifecycle lock changes the core or its config
+    fn publish_clash_api(&self, inner: &Option<ServiceInner>) {
+        *self.clash_api.write().unwrap() = inner.as_ref().map(|inner| ClashAPISnapshot {
+            client: inner.clash_api.clone(),
+            config_id: inner.source.id.clone(),
+            ready: inner.ready.clone(),
+        });
+    }
+
+    // Client of the running core, with the listen address and secret extracted by check_config
+    pub(crate) fn clash_api_client(&self) -> Result<super::ClashAPIClient, super::Error> {
+        self.running_clash_api_client().map(|(client, _)| client)
+    }
+
+    // Also return the id of the running config
+    fn running_clash_api_client(&self) -> Result<(super::ClashAPIClient, String), super::Error> {
+        match self.clash_api.read().unwrap().as_ref() {
+            Some(snapshot) if *snapshot.ready.borrow() => {
+                Ok((snapshot.client.clone(), snapshot.config_id.clone()))
+            }
+            _ => Err(super::Error::ServiceNotRunning),
+        }
+    }
+
+    pub(crate) async fn close_connection(&self, id: &str) -> Result<(), super::Error> {
+        self.clash_api_client()?
+            .close_connection(id)
+            .await
+            .map_err(super::Error::ClashAPIFailed)
+    }
+
+    // Close the connections matching the filters, or all without filters, return the closed ids
+    pub(crate) async fn close_connections(
+        &self,
+        query: &super::ConnectionQuery,
+    ) -> Result<Vec<String>, super::Error> {
+        let client = self.clash_api_client()?;
+        let connections = client
+            .connections()
+            .await
+            .map_err(super::Error::ClashAPIFailed)?;
+        if !query.is_filtered() {
+            client
+                .close_all_connections()
+                .await
+                .map_err(super::Error::ClashAPIFailed)?;
+            log::info!("service: closed all {} connections", connections.len());
+            return Ok(connections.into_iter().map(|c| c.id).collect());
+        }
+        let ids = connections
+            .into_iter()
+            .filter(|c| query.matches(c))
+            .map(|c| c.id)
+            .collect::<Vec<_>>();
+        futures_util::future::try_join_all(ids.iter().map(|id| client.close_connection(id)))
+            .await
+            .map_err(super::Error::ClashAPIFailed)?;
+        log::info!("service: closed {} connections", ids.len());
+        Ok(ids)
+    }
+
+    // Outbounds with members, in the order of the GLOBAL group
+    pub(crate) async fn get_proxy_groups(&self) -> Result<Vec<super::ClashAPIProxy>, super::Error> {
+        let mut proxies = self
+            .clash_api_client()?
+            .proxies()
+            .await
+            .map_err(super::Error::ClashAPIFailed)?;
+        let order = proxies
+            .get("GLOBAL")
+            .map(|global| global.all.clone())
+            .unwrap_or_default();
+        let mut groups = proxies
+            .drain()
+            .map(|(_, proxy)| proxy)
+            .filter(|proxy| !proxy.all.is_empty())
+            .collect::<Vec<_>>();
+        groups.sort_by_key(|group| {
+            (
+                order
+                    .iter()
+                    .position(|name| name == &group.name)
+                    .unwrap_or(usize::MAX),
+                group.name.clone(),
+            )
+        });
+        Ok(groups)
+    }
+
+    // Saved for the running config and re-applied when its core is ready
+    pub(crate) async fn select_proxy(&self, group: &str, name: &str) -> Result<(), super::Error> {
+        let (client, config_id) = self.running_clash_api_client()?;
+        client
+            .select_proxy(group, name)
+            .await
+            .map_err(super::Error::ClashAPIFailed)?;
+        log::info!("service: proxy group {} selected {}", group, name);
+        database::set_proxy_selection(
+            &self.manager.get_database(),
+            database::ProxySelection {
+                config_id,
+                group: group.to_string(),
+                selected: name.to_string(),
+            },
+        )
+        .await
+        .map_err(|e| super::Error::SetProxySelectionFailed(e.to_string()))
+    }
+
+    pub(crate) async fn get_clash_mode(
+        &self,
+    ) -> Result<super::ClashAPIConfigsResult, super::Error> {
+        self.clash_api_client()?
+            .configs()
+            .await
+            .map_err(super::Error::ClashAPIFailed)
+    }
+
+    // Saved for the running config and re-applied when its core is ready, returns the mode
+    // as named by the core
+    pub(crate) async fn set_clash_mode(&self, mode: &str) -> Result<String, super::Error> {
+        let (client, config_id) = self.running_clash_api_client()?;
+        let configs = client
+            .configs()
+            .await
+            .map_err(super::Error::ClashAPIFailed)?;
+        // The core matches modes case insensitively and ignores unknown ones
+        let mode = if configs.mode_list.is_empty() {
+            mode.to_string()
+        } else {
+            configs
+                .mode_list
+                .iter()
+                .find(|m| m.eq_ignore_ascii_case(mode))
+                .cloned()
+                .ok_or_else(|| super::Error::InvalidClashMode(mode.to_string()))?
+        };
+        client
+            .set_mode(&mode)
+            .await
+            .map_err(super::Error::ClashAPIFailed)?;
+        log::info!("service: clash mode set to {}", mode);
+        database::set_clash_mode(
+            &self.manager.get_database(),
+            database::ClashMode {
+                config_id,
+                mode: mode.clone(),
+            },
+        )
+        .await
+        .map_err(|e| super::Error::SetClashModeFailed(e.to_string()))?;
+        Ok(mode)
+    }
+
+    pub(crate) async fn dns_query(
+        &self,
+        name: &str,
+        query_type: &str,
+    ) -> Result<serde_json::Value, super::Error> {
+        self.clash_api_client()?
+            .dns_query(name, query_type)
+            .await
+            .map_err(super::Error::ClashAPIFailed)
+    }
+
+    // Both caches are tried, older cores have no dns cache flush
+    pub(crate) async fn flush_cache(&self, dns: bool, fakeip: bool) -> Result<(), super::Error> {
+        let client = self.clash_api_client()?;
+        let mut errors = Vec::new();
+        if dns {
+            match client.flush_dns_cache().await {
+                Ok(_) => log::info!("service: dns cache flushed"),
+                Err(e) => errors.push(format!("flush dns cache: {}", e)),
+            }
+        }
+        if fakeip {
+            match client.flush_fakeip_cache().await {
+                Ok(_) => log::info!("service: fakeip cache flushed"),
+                Err(e) => errors.push(format!("flush fakeip cache: {}", e)),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(super::Error::ClashAPIFailed(errors.join(", ")));
+        }
+        Ok(())
+    }
+
+    // Results are saved with the running config
+    pub(crate) async fn test_delay(
+        &self,
+        option: &super::DelayTestOption,
+    ) -> Result<Vec<database::DelayTest>, super::Error> {
+        let (client, config_id) = self.running_clash_api_client()?;
+        let results = super::run_delay_test(&client, option).await;
+        let time = chrono::Utc::now().timestamp_millis();
+        let tests = results
+            .into_iter()
+            .map(|(outbound, result)| {
+                let (delay, error) = match result {
+                    Ok(delay) => (Some(delay), None),
+                    Err(e) => (None, Some(e)),
+                };
+                database::DelayTest {
+                    id: String::new(),
+                    instance: self.name.clone(),
+                    config_id: config_id.clone(),
+                    outbound,
+                    url: option.url.clone(),
+                    delay,
+                    error,
+                    time,
+                }
+            })
+            .collect();
+        database::add_delay_test(&self.manager.get_database(), tests)
+            .await
+            .map_err(|e| super::Error::SaveDelayTestFailed(e.to_string()))
+    }
+
     pub(crate) fn log_queue_listener(&self) -> super::LogQueueListener<String> {
         self.log_queue.subscribe()
     }
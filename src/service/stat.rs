@@ -0,0 +1,174 @@
+use crate::database;
+
+// Status values read at one sample
+pub(crate) struct StatValues {
+    pub(crate) upload_total: u64,   // B, since the core started
+    pub(crate) download_total: u64, // B, since the core started
+    pub(crate) upload_speed: u64,   // B/s
+    pub(crate) download_speed: u64, // B/s
+    pub(crate) memory: u64,         // B
+    pub(crate) connections: u64,
+}
+
+// Accumulates samples into minute stats, traffic is the delta of the totals
+pub(crate) struct StatSampler {
+    instance: String,
+    bucket: i64, // start of the current minute (ms)
+    last_upload_total: u64,
+    last_download_total: u64,
+    stat: database::Stat,
+}
+
+impl StatSampler {
+    pub(crate) fn new(instance: String, now: i64) -> Self {
+        let bucket = database::StatGranularity::Minute.bucket(now);
+        Self {
+            stat: Self::empty(&instance, bucket),
+            instance,
+            bucket,
+            last_upload_total: 0,
+            last_download_total: 0,
+        }
+    }
+
+    fn empty(instance: &str, bucket: i64) -> database::Stat {
+        database::Stat {
+            instance: instance.to_string(),
+            granularity: database::StatGranularity::Minute.as_str().to_string(),
+            time: bucket,
+            upload: 0,
+            download: 0,
+            upload_speed: 0,
+            download_speed: 0,
+            upload_speed_max: 0,
+            download_speed_max: 0,
+            memory: 0,
+            memory_max: 0,
+            connections: 0,
+            connections_max: 0,
+            samples: 0,
+        }
+    }
+
+    // Return the finished minute when `now` (ms) is in the next one
+    pub(crate) fn sample(&mut self, now: i64, values: StatValues) -> Option<database::Stat> {
+        let bucket = database::StatGranularity::Minute.bucket(now);
+        let finished = if bucket != self.bucket {
+            self.bucket = bucket;
+            Some(std::mem::replace(
+                &mut self.stat,
+                Self::empty(&self.instance, bucket),
+            ))
+        } else {
+            None
+        };
+        // Totals start over if the core resets its clash api
+        let delta = |total: u64, last: u64| total.checked_sub(last).unwrap_or(total) as i64;
+        let stat = &mut self.stat;
+        stat.upload += delta(values.upload_total, self.last_upload_total);
+        stat.download += delta(values.download_total, self.last_download_total);
+        self.last_upload_total = values.upload_total;
+        self.last_download_total = values.download_total;
+        // Running averages, weighted by the samples
+        let avg =
+            |avg: i64, value: u64, samples: i64| (avg * samples + value as i64) / (samples + 1);
+        stat.upload_speed = avg(stat.upload_speed, values.upload_speed, stat.samples);
+        stat.download_speed = avg(stat.download_speed, values.download_speed, stat.samples);
+        stat.memory = avg(stat.memory, values.memory, stat.samples);
+        stat.connections = avg(stat.connections, values.connections, stat.samples);
+        stat.upload_speed_max = stat.upload_speed_max.max(values.upload_speed as i64);
+        stat.download_speed_max = stat.download_speed_max.max(values.download_speed as i64);
+        stat.memory_max = stat.memory_max.max(values.memory as i64);
+        stat.connections_max = stat.connections_max.max(values.connections as i64);
+        stat.samples += 1;
+        finished.filter(|stat| stat.samples > 0)
+    }
+
+    // The part of the current minute sampled so far, the rest is added to the same bucket later
+    pub(crate) fn flush(&mut self) -> Option<database::Stat> {
+        let stat = std::mem::replace(&mut self.stat, Self::empty(&self.instance, self.bucket));
+        Some(stat).filter(|stat| stat.samples > 0)
+    }
+
+    // The unfinished minute, when the core stops
+    pub(crate) fn finish(self) -> Option<database::Stat> {
+        Some(self.stat).filter(|stat| stat.samples > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: i64 = 60 * 1000;
+
+    fn values(upload_total: u64, download_total: u64, speed: u64) -> StatValues {
+        StatValues {
+            upload_total,
+            download_total,
+            upload_speed: speed,
+            download_speed: speed * 2,
+            memory: 1000,
+            connections: speed,
+        }
+    }
+
+    #[test]
+    fn test_sample_minute() {
+        let start = 10 * MINUTE;
+        let mut sampler = StatSampler::new("default".to_string(), start + 1000);
+        assert!(sampler.sample(start + 5000, values(100, 200, 10)).is_none());
+        assert!(sampler
+            .sample(start + 10000, values(150, 260, 30))
+            .is_none());
+        // The first sample of the next minute finishes the previous one
+        let stat = sampler
+            .sample(start + MINUTE, values(170, 300, 50))
+            .unwrap();
+        assert_eq!(stat.time, start);
+        assert_eq!(stat.granularity, "minute");
+        assert_eq!(stat.instance, "default");
+        assert_eq!((stat.upload, stat.download), (150, 260));
+        assert_eq!((stat.upload_speed, stat.upload_speed_max), (20, 30));
+        assert_eq!((stat.download_speed, stat.download_speed_max), (40, 60));
+        assert_eq!((stat.connections, stat.connections_max), (20, 30));
+        assert_eq!(stat.samples, 2);
+        let stat = sampler.finish().unwrap();
+        assert_eq!(stat.time, start + MINUTE);
+        assert_eq!((stat.upload, stat.download), (20, 40));
+        assert_eq!(stat.samples, 1);
+    }
+
+    #[test]
+    fn test_sample_total_reset() {
+        let mut sampler = StatSampler::new("default".to_string(), 0);
+        sampler.sample(1000, values(1000, 2000, 0));
+        // The core restarted its counters, the new totals are the traffic since
+        sampler.sample(2000, values(300, 100, 0));
+        let stat = sampler.finish().unwrap();
+        assert_eq!((stat.upload, stat.download), (1300, 2100));
+    }
+
+    #[test]
+    fn test_sample_flush() {
+        let mut sampler = StatSampler::new("default".to_string(), 0);
+        assert!(sampler.flush().is_none());
+        sampler.sample(1000, values(100, 200, 10));
+        let stat = sampler.flush().unwrap();
+        assert_eq!((stat.time, stat.upload, stat.samples), (0, 100, 1));
+        // The totals are still the base of the next delta
+        sampler.sample(2000, values(150, 200, 10));
+        let stat = sampler.finish().unwrap();
+        assert_eq!((stat.time, stat.upload, stat.samples), (0, 50, 1));
+    }
+
+    #[test]
+    fn test_sample_skipped_minute() {
+        let mut sampler = StatSampler::new("default".to_string(), 0);
+        assert!(sampler.finish().is_none());
+        let mut sampler = StatSampler::new("default".to_string(), 0);
+        // No sample in the first minute, nothing to finish
+        assert!(sampler.sample(3 * MINUTE, values(1, 1, 1)).is_none());
+        assert_eq!(sampler.finish().unwrap().time, 3 * MINUTE);
+    }
+}